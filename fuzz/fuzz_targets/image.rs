@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mangaverse_sources::mirror::image::dimensions;
+use mangaverse_sources::mirror::sniff_content_type;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = sniff_content_type(data);
+    for content_type in ["image/png", "image/gif", "image/jpeg", "image/webp"] {
+        let _ = dimensions(data, content_type);
+    }
+});
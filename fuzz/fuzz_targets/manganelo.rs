@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mangaverse_entity::models::source::SourceTable;
+use mangaverse_sources::db::genre::GenreMap;
+use mangaverse_sources::manganelo::entity::{parse_genres, parse_manga, parse_pages};
+use scraper::Html;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        let doc = Html::parse_document(s);
+        let sc = SourceTable {
+            id: String::new(),
+            name: "manganelo".to_string(),
+            priority: 0,
+        };
+        let map = GenreMap::default();
+
+        let _ = parse_genres(&doc);
+        let _ = parse_manga(&doc, String::new(), &sc, &map);
+        let _ = parse_pages(&doc);
+    }
+});
@@ -0,0 +1,24 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mangaverse_sources::parse::chapter::ChapterNumber;
+use mangaverse_sources::parse::date::{DateFormat, ENGLISH};
+use mangaverse_sources::parse::status::normalize_status;
+use mangaverse_sources::parse::text::{clean_line, clean_text, truncate};
+
+const DATES: DateFormat = DateFormat {
+    locale: &ENGLISH,
+    utc_offset: 0,
+    formats: &["%b %d,%Y - %H:%M", "%d/%m/%Y"],
+};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        let _ = DATES.parse(s);
+        let _ = ChapterNumber::parse(s).map(|f| f.to_string());
+        let _ = ChapterNumber::from_url(s);
+        let _ = normalize_status("manganelo", s);
+        let _ = clean_line(s);
+        let _ = truncate(clean_text(s).as_str(), 255);
+    }
+});
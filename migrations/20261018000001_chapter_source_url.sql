@@ -0,0 +1,3 @@
+-- Remember where each chapter was scraped from so refreshes can match chapters by identity
+ALTER TABLE chapter ADD COLUMN source_url VARCHAR(1024) NULL;
+CREATE INDEX chapter_manga_source_url ON chapter(manga_id, source_url(255));
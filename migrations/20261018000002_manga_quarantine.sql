@@ -0,0 +1,11 @@
+-- Scrapes that tripped an update guard wait here for review instead of being written. scrape holds
+-- what the scrape would have changed, as a JSON MangaDiff, so reviewers see more than the reason.
+CREATE TABLE manga_quarantine (
+    quarantine_id VARCHAR(36) NOT NULL PRIMARY KEY,
+    manga_id VARCHAR(36) NOT NULL,
+    reason TEXT NOT NULL,
+    scrape LONGTEXT NULL,
+    created_at BIGINT NOT NULL,
+    resolved_at BIGINT NULL,
+    INDEX manga_quarantine_manga (manga_id, resolved_at)
+);
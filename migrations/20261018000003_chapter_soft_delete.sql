@@ -0,0 +1,5 @@
+-- Chapters and page sets are marked removed instead of being deleted
+ALTER TABLE chapter ADD COLUMN removed_at BIGINT NULL;
+ALTER TABLE chapter_page ADD COLUMN removed_at BIGINT NULL;
+CREATE INDEX chapter_manga_removed ON chapter(manga_id, removed_at);
+CREATE INDEX chapter_page_chapter_removed ON chapter_page(chapter_id, removed_at);
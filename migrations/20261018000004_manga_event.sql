@@ -0,0 +1,12 @@
+-- Append-only audit trail of every write made to a manga
+CREATE TABLE manga_event (
+    event_id VARCHAR(36) NOT NULL PRIMARY KEY,
+    manga_id VARCHAR(36) NOT NULL,
+    source_id VARCHAR(36) NULL,
+    kind VARCHAR(32) NOT NULL,
+    before_summary TEXT NOT NULL,
+    after_summary TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    INDEX manga_event_manga (manga_id, created_at),
+    INDEX manga_event_time (created_at)
+);
@@ -0,0 +1,23 @@
+-- Which manga contributed each title, so a title can be dropped once no manga in the group lists it
+CREATE TABLE manga_title (
+    manga_id VARCHAR(36) NOT NULL,
+    title VARCHAR(512) NOT NULL,
+    PRIMARY KEY (manga_id, title)
+);
+
+-- A title that is a manga's own name belongs to that manga. Every other title goes to the group's
+-- main manga, or any manga of the group when none is main, which in a group of one is its only manga.
+INSERT IGNORE INTO manga_title(manga_id, title)
+SELECT manga.manga_id, title.title FROM title, manga
+WHERE title.linked_id = manga.linked_id AND title.title = manga.name;
+
+INSERT IGNORE INTO manga_title(manga_id, title)
+SELECT (
+    SELECT m.manga_id FROM manga m WHERE m.linked_id = title.linked_id
+    ORDER BY m.is_main DESC, m.manga_id ASC LIMIT 1
+), title.title FROM title
+WHERE EXISTS (SELECT 1 FROM manga m WHERE m.linked_id = title.linked_id)
+AND NOT EXISTS (
+    SELECT 1 FROM manga_title mt, manga m
+    WHERE mt.manga_id = m.manga_id AND m.linked_id = title.linked_id AND mt.title = title.title
+);
@@ -0,0 +1,6 @@
+-- Normalized form of each title used for fuzzy cross-source linking.
+-- Rows that predate this column are filled in by running db::link::backfill_normalized_titles once.
+ALTER TABLE title ADD COLUMN normalized_title VARCHAR(512) NULL;
+CREATE INDEX title_normalized ON title(normalized_title);
+-- Candidate groups sharing a title word are looked up through this instead of scanning with LIKE.
+CREATE FULLTEXT INDEX title_normalized_words ON title(normalized_title);
@@ -0,0 +1,17 @@
+-- Cross-source links that scored too low to be made automatically, waiting for a human
+CREATE TABLE link_review (
+    review_id VARCHAR(36) NOT NULL PRIMARY KEY,
+    manga_id VARCHAR(36) NOT NULL,
+    linked_id VARCHAR(36) NOT NULL,
+    score DOUBLE NOT NULL,
+    title_score DOUBLE NOT NULL,
+    author_score DOUBLE NULL,
+    chapter_score DOUBLE NULL,
+    matching_titles TEXT NOT NULL,
+    matching_authors TEXT NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    created_at BIGINT NOT NULL,
+    resolved_at BIGINT NULL,
+    INDEX link_review_status (status, created_at),
+    INDEX link_review_manga (manga_id)
+);
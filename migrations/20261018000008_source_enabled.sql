@@ -0,0 +1,2 @@
+-- Disabled sources are never picked as the main manga of a group while another source is there
+ALTER TABLE source ADD COLUMN is_enabled BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,2 @@
+-- Why a candidate link needs a human: too low a score, or too close to another group's
+ALTER TABLE link_review ADD COLUMN reason VARCHAR(16) NOT NULL DEFAULT 'low_score';
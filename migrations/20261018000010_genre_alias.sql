@@ -0,0 +1,17 @@
+-- Maps the genre names a source uses onto one canonical genre row
+CREATE TABLE genre_alias (
+    source_id VARCHAR(36) NOT NULL,
+    alias VARCHAR(255) NOT NULL,
+    genre_id VARCHAR(36) NOT NULL,
+    PRIMARY KEY (source_id, alias)
+);
+
+-- Genre names seen on manga pages that map to nothing, awaiting an alias
+CREATE TABLE unknown_genre (
+    source_id VARCHAR(36) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    occurrences INT NOT NULL DEFAULT 0,
+    first_seen BIGINT NOT NULL,
+    last_seen BIGINT NOT NULL,
+    PRIMARY KEY (source_id, name)
+);
@@ -0,0 +1,12 @@
+-- The genres each source offers and where to browse them; removed_at is set once a genre is gone from the site
+CREATE TABLE source_genre (
+    source_id VARCHAR(36) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    genre_id VARCHAR(36) NOT NULL,
+    browse_url VARCHAR(1024),
+    first_seen BIGINT NOT NULL,
+    last_seen BIGINT NOT NULL,
+    removed_at BIGINT,
+    PRIMARY KEY (source_id, name),
+    INDEX (genre_id)
+);
@@ -0,0 +1,20 @@
+-- Normalized status next to the raw text in manga.status; values are those of parse::status::Status
+ALTER TABLE manga ADD COLUMN publication_status VARCHAR(16) NOT NULL DEFAULT 'unknown';
+ALTER TABLE manga_listing ADD COLUMN publication_status VARCHAR(16) NOT NULL DEFAULT 'unknown';
+
+UPDATE manga SET publication_status = CASE lower(trim(status))
+    WHEN 'ongoing' THEN 'ongoing'
+    WHEN 'completed' THEN 'completed'
+    WHEN 'complete' THEN 'completed'
+    WHEN 'finished' THEN 'completed'
+    WHEN 'hiatus' THEN 'hiatus'
+    WHEN 'on hiatus' THEN 'hiatus'
+    WHEN 'cancelled' THEN 'cancelled'
+    WHEN 'canceled' THEN 'cancelled'
+    WHEN 'dropped' THEN 'cancelled'
+    WHEN 'discontinued' THEN 'cancelled'
+    ELSE 'unknown'
+END;
+
+UPDATE manga_listing, manga SET manga_listing.publication_status = manga.publication_status
+WHERE manga_listing.manga_id = manga.manga_id;
@@ -0,0 +1,15 @@
+-- Page images copied to our own storage, keyed by the sha256 of their bytes. Each storage keeps
+-- its own copy, so a page mirrored before a storage switch is copied into the new one.
+CREATE TABLE page_blob (
+    hash CHAR(64) NOT NULL,
+    storage VARCHAR(32) NOT NULL,
+    storage_key VARCHAR(255) NOT NULL,
+    url VARCHAR(1024) NOT NULL,
+    content_type VARCHAR(64) NOT NULL,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (hash, storage)
+);
+
+ALTER TABLE chapter_page ADD COLUMN mirrored_at BIGINT NULL;
+ALTER TABLE chapter_page ADD COLUMN mirror_attempts INT NOT NULL DEFAULT 0;
+ALTER TABLE chapter_page ADD COLUMN mirror_error VARCHAR(1024) NULL;
@@ -0,0 +1,12 @@
+-- What a page's image turned out to be when it was last fetched, filled in by mirror::inspect
+ALTER TABLE chapter_page ADD COLUMN width INT UNSIGNED NULL;
+ALTER TABLE chapter_page ADD COLUMN height INT UNSIGNED NULL;
+ALTER TABLE chapter_page ADD COLUMN bytes BIGINT UNSIGNED NULL;
+ALTER TABLE chapter_page ADD COLUMN mime_type VARCHAR(64) NULL;
+ALTER TABLE chapter_page ADD COLUMN content_hash CHAR(64) NULL;
+ALTER TABLE chapter_page ADD COLUMN inspected_at BIGINT NULL;
+ALTER TABLE chapter_page ADD COLUMN inspect_error VARCHAR(1024) NULL;
+CREATE INDEX chapter_page_uninspected ON chapter_page(inspected_at, removed_at);
+-- Also keys a page's mirrored blob, whether a page is mirrored into a storage is whether that
+-- storage has a blob for its hash
+CREATE INDEX chapter_page_unmirrored ON chapter_page(content_hash, removed_at, mirror_attempts);
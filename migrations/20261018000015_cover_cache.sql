@@ -0,0 +1,16 @@
+-- Covers copied to our own storage with thumbnails, so the listing does not hotlink source CDNs.
+-- cover_cached_from is the cover_url the cached files were made from; a mismatch means a refresh is due.
+ALTER TABLE manga_listing ADD COLUMN cover_cached_from VARCHAR(1024) NULL;
+ALTER TABLE manga_listing ADD COLUMN cover_original VARCHAR(1024) NULL;
+ALTER TABLE manga_listing ADD COLUMN cover_small VARCHAR(1024) NULL;
+ALTER TABLE manga_listing ADD COLUMN cover_medium VARCHAR(1024) NULL;
+ALTER TABLE manga_listing ADD COLUMN cover_large VARCHAR(1024) NULL;
+ALTER TABLE manga_listing ADD COLUMN cover_error VARCHAR(1024) NULL;
+-- cover_failed_from is the cover_url that last failed to cache, cover_attempts how many times in a
+-- row, and cover_retry_at when CoverCache::run should try it again, null when it should not be.
+ALTER TABLE manga_listing ADD COLUMN cover_failed_from VARCHAR(1024) NULL;
+ALTER TABLE manga_listing ADD COLUMN cover_attempts INT NOT NULL DEFAULT 0;
+ALTER TABLE manga_listing ADD COLUMN cover_retry_at BIGINT NULL;
+
+-- covers listed before caching existed are left to CoverCache::run
+UPDATE manga_listing SET cover_retry_at = 0;
@@ -0,0 +1,5 @@
+-- Canonical number chapters are paired by, chapter_number keeps the site's own text for display
+ALTER TABLE chapter ADD COLUMN number_key VARCHAR(255) NULL;
+CREATE INDEX chapter_manga_number_key ON chapter(manga_id, number_key);
+-- numbers were stored in their canonical form until now
+UPDATE chapter SET number_key = NULLIF(TRIM(chapter_number), '');
@@ -0,0 +1,128 @@
+use itertools::Itertools;
+use mangaverse_entity::models::manga::MangaTable;
+use sqlx::{pool::PoolConnection, MySql, QueryBuilder};
+use uuid::Uuid;
+
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthorRole {
+    Author,
+    Artist,
+}
+
+impl AuthorRole {
+    fn table(&self) -> &'static str {
+        match self {
+            AuthorRole::Author => "manga_author",
+            AuthorRole::Artist => "manga_artist",
+        }
+    }
+
+    /// Authors are stored uppercase and artists lowercase
+    pub fn normalize(&self, name: &str) -> String {
+        match self {
+            AuthorRole::Author => name.trim().to_uppercase(),
+            AuthorRole::Artist => name.trim().to_lowercase(),
+        }
+    }
+}
+
+/// Brings scraped authors and artists into the shape they are stored in
+pub fn normalize_people(mng: &mut MangaTable<'_>) {
+    for (list, role) in [
+        (&mut mng.authors, AuthorRole::Author),
+        (&mut mng.artists, AuthorRole::Artist),
+    ] {
+        *list = list
+            .iter()
+            .map(|f| role.normalize(f))
+            .filter(|f| !f.is_empty())
+            .unique()
+            .collect();
+    }
+}
+
+/// Makes sure every name has a row in `author`
+pub async fn insert_authors<'a>(
+    names: impl IntoIterator<Item = &'a String>,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    let names = names.into_iter().collect::<Vec<_>>();
+
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    let mut q = QueryBuilder::new("INSERT into author(author_id, name) ");
+
+    q.push_values(names, |mut b, author| {
+        b.push_bind(Uuid::new_v4().to_string());
+        b.push_bind(author);
+    });
+
+    q.push(" ON DUPLICATE KEY update author_id = author_id");
+
+    q.build().execute(&mut *conn).await?;
+
+    Ok(())
+}
+
+/// Links existing authors to a manga in the given role
+pub async fn add_manga_authors(
+    manga_id: &str,
+    names: &[String],
+    role: AuthorRole,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    let mut q = QueryBuilder::new("INSERT into ");
+    q.push(role.table());
+    q.push("(manga_id, author_id) select ");
+    q.push_bind(manga_id);
+    q.push(" as manga_id, author.author_id as author_id from author where author.name IN (");
+
+    let mut sep = q.separated(',');
+
+    for t in names {
+        sep.push_bind(t);
+    }
+
+    q.push(')');
+
+    q.build().execute(&mut *conn).await?;
+
+    Ok(())
+}
+
+pub async fn remove_manga_authors(
+    manga_id: &str,
+    names: &[String],
+    role: AuthorRole,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    let mut q = QueryBuilder::new("DELETE from ");
+    q.push(role.table());
+    q.push(" where manga_id = ");
+    q.push_bind(manga_id);
+    q.push(" and author_id IN (select author_id from author where name IN (");
+
+    let mut sep = q.separated(',');
+
+    for t in names {
+        sep.push_bind(t);
+    }
+
+    q.push("))");
+
+    q.build().execute(&mut *conn).await?;
+
+    Ok(())
+}
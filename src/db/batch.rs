@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use mangaverse_entity::models::chapter::ChapterTable;
+use mangaverse_entity::models::manga::MangaTable;
+use mangaverse_entity::models::page::PageTable;
+use sqlx::mysql::{MySqlConnection, MySqlRow};
+use sqlx::pool::PoolConnection;
+use sqlx::types::chrono::NaiveDateTime;
+use sqlx::{FromRow, MySql, QueryBuilder, Row};
+
+use crate::{Context, Result};
+
+use super::manga::{source_by_id, MangaTableWrapper};
+use super::mirror::prefer_mirrors;
+
+/// Ids per `IN (...)` list, well below what MySQL accepts for placeholders
+pub(crate) const BATCH_SIZE: usize = 1000;
+
+/// Loads many manga with all their relations, chapters and pages included, in input order.
+/// Ids that don't exist are skipped.
+pub async fn get_mangas_from_ids<'a>(
+    ids: &[String],
+    conn: &mut PoolConnection<MySql>,
+    c: &'a Context,
+) -> Result<Vec<MangaTable<'a>>> {
+    load(ids, true, None, conn, c).await
+}
+
+/// Same as `get_mangas_from_ids`, with pages pointing at their copies in the mirror storage named
+/// `storage` where one exists. Meant for serving readers, refreshes must keep comparing against
+/// the source urls.
+pub async fn get_mangas_from_ids_mirrored<'a>(
+    ids: &[String],
+    storage: &str,
+    conn: &mut PoolConnection<MySql>,
+    c: &'a Context,
+) -> Result<Vec<MangaTable<'a>>> {
+    load(ids, true, Some(storage), conn, c).await
+}
+
+/// Same as `get_mangas_from_ids` but leaves `chapters` empty
+pub async fn get_mangas_from_ids_light<'a>(
+    ids: &[String],
+    conn: &mut PoolConnection<MySql>,
+    c: &'a Context,
+) -> Result<Vec<MangaTable<'a>>> {
+    load(ids, false, None, conn, c).await
+}
+
+async fn load<'a>(
+    ids: &[String],
+    with_chapters: bool,
+    mirror: Option<&str>,
+    conn: &mut PoolConnection<MySql>,
+    c: &'a Context,
+) -> Result<Vec<MangaTable<'a>>> {
+    let mut out = Vec::with_capacity(ids.len());
+
+    for chunk in ids.chunks(BATCH_SIZE) {
+        out.extend(load_chunk(chunk, with_chapters, mirror, conn, c).await?);
+    }
+
+    Ok(out)
+}
+
+pub(crate) fn push_in<'a>(q: &mut QueryBuilder<'a, MySql>, items: impl Iterator<Item = &'a str>) {
+    q.push('(');
+    let mut sep = q.separated(',');
+    for t in items {
+        sep.push_bind(t);
+    }
+    q.push(')');
+}
+
+/// Runs a query returning `(key, value)` string pairs and groups the values by key
+pub(crate) async fn fetch_grouped(
+    mut q: QueryBuilder<'_, MySql>,
+    conn: &mut MySqlConnection,
+) -> Result<HashMap<String, Vec<String>>> {
+    let mut out: HashMap<String, Vec<String>> = HashMap::new();
+
+    for r in q.build().fetch_all(&mut *conn).await? {
+        out.entry(r.try_get::<String, usize>(0)?)
+            .or_default()
+            .push(r.try_get::<String, usize>(1)?);
+    }
+
+    Ok(out)
+}
+
+struct ChapterRow {
+    chapter_id: String,
+    chapter_name: String,
+    chapter_number: String,
+    updated_at: Option<NaiveDateTime>,
+    manga_id: String,
+    last_watch_time: i64,
+    sequence_number: i32,
+}
+
+impl FromRow<'_, MySqlRow> for ChapterRow {
+    fn from_row(row: &'_ MySqlRow) -> std::result::Result<Self, sqlx::Error> {
+        Ok(ChapterRow {
+            chapter_id: row.try_get("chapter_id")?,
+            chapter_name: row.try_get("chapter_name")?,
+            chapter_number: row.try_get("chapter_number")?,
+            updated_at: row.try_get("updated_at")?,
+            manga_id: row.try_get("manga_id")?,
+            last_watch_time: row.try_get("last_watch_time")?,
+            sequence_number: row.try_get("sequence_number")?,
+        })
+    }
+}
+
+/// Loads the live chapters of several manga in order, each with its current pages, by manga id.
+/// With a `mirror` storage named, pages mirrored there point at their copies.
+pub(crate) async fn load_chapters(
+    ids: &[&str],
+    mirror: Option<&str>,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<HashMap<String, Vec<ChapterTable>>> {
+    let mut q = QueryBuilder::new("SELECT chapter_id, chapter_name, chapter_number, updated_at, manga_id, last_watch_time, sequence_number from chapter where removed_at is null and manga_id IN ");
+    push_in(&mut q, ids.iter().copied());
+    q.push(" order by sequence_number ASC");
+
+    let chapters = q
+        .build()
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(ChapterRow::from_row)
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let mut q = QueryBuilder::new("SELECT chapter_page.chapter_page_id, chapter_page.url, chapter_page.page_number, chapter_page.chapter_id from chapter_page, chapter where chapter_page.chapter_id = chapter.chapter_id and chapter.removed_at is null and chapter_page.removed_at is null and chapter.manga_id IN ");
+    push_in(&mut q, ids.iter().copied());
+    q.push(" order by chapter_page.page_number ASC");
+
+    let mut pages: HashMap<String, Vec<PageTable>> = HashMap::new();
+
+    for r in q.build().fetch_all(&mut *conn).await? {
+        let p = PageTable {
+            id: r.try_get("chapter_page_id")?,
+            url: r.try_get("url")?,
+            page_number: r.try_get("page_number")?,
+            chapter_id: r.try_get("chapter_id")?,
+        };
+        pages.entry(p.chapter_id.clone()).or_default().push(p);
+    }
+
+    let mut chapters: Vec<ChapterTable> = chapters
+        .into_iter()
+        .map(|f| ChapterTable {
+            pages: pages.remove(&f.chapter_id).unwrap_or_default(),
+            chapter_id: f.chapter_id,
+            chapter_name: f.chapter_name,
+            chapter_number: f.chapter_number,
+            last_watch_time: f.last_watch_time,
+            manga_id: f.manga_id,
+            sequence_number: f.sequence_number,
+            updated_at: f.updated_at,
+        })
+        .collect();
+
+    if let Some(storage) = mirror {
+        prefer_mirrors(chapters.as_mut_slice(), storage, conn).await?;
+    }
+
+    let mut out: HashMap<String, Vec<ChapterTable>> = HashMap::new();
+
+    for f in chapters {
+        out.entry(f.manga_id.clone()).or_default().push(f);
+    }
+
+    Ok(out)
+}
+
+async fn load_chunk<'a>(
+    ids: &[String],
+    with_chapters: bool,
+    mirror: Option<&str>,
+    conn: &mut PoolConnection<MySql>,
+    c: &'a Context,
+) -> Result<Vec<MangaTable<'a>>> {
+    let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+
+    let mut q = QueryBuilder::new("SELECT * from manga where manga_id IN ");
+    push_in(&mut q, ids.iter().copied());
+
+    let mut found: HashMap<String, MangaTableWrapper<'a>> = HashMap::new();
+
+    for r in q.build().fetch_all(&mut *conn).await? {
+        let w = MangaTableWrapper::from_row(&r)?;
+        found.insert(w.contents.id.clone(), w);
+    }
+
+    let linked: Vec<String> = found
+        .values()
+        .map(|f| f.contents.linked_id.clone())
+        .collect();
+
+    let mut titles = HashMap::new();
+
+    if !linked.is_empty() {
+        let mut q = QueryBuilder::new("SELECT linked_id, title from title where linked_id IN ");
+        push_in(&mut q, linked.iter().map(String::as_str));
+        titles = fetch_grouped(q, conn).await?;
+    }
+
+    let mut q = QueryBuilder::new("SELECT manga_author.manga_id, author.name from author, manga_author where manga_author.author_id = author.author_id and manga_author.manga_id IN ");
+    push_in(&mut q, ids.iter().copied());
+    let mut authors = fetch_grouped(q, conn).await?;
+
+    let mut q = QueryBuilder::new("SELECT manga_artist.manga_id, author.name from author, manga_artist where manga_artist.author_id = author.author_id and manga_artist.manga_id IN ");
+    push_in(&mut q, ids.iter().copied());
+    let mut artists = fetch_grouped(q, conn).await?;
+
+    let mut q = QueryBuilder::new("SELECT manga_genre.manga_id, genre.name from genre, manga_genre where manga_genre.genre_id = genre.genre_id and manga_genre.manga_id IN ");
+    push_in(&mut q, ids.iter().copied());
+    let mut genres = fetch_grouped(q, conn).await?;
+
+    let mut chapters = if with_chapters {
+        load_chapters(ids.as_slice(), mirror, conn).await?
+    } else {
+        HashMap::new()
+    };
+
+    let mut out = Vec::with_capacity(found.len());
+
+    for id in ids {
+        let mut r = match found.remove(id) {
+            Some(r) => r,
+            None => continue,
+        };
+
+        r.contents.source = source_by_id(c, r.source_id.as_str())?;
+
+        r.contents.titles = titles
+            .get(&r.contents.linked_id)
+            .cloned()
+            .unwrap_or_default();
+        r.contents.authors = authors.remove(id).unwrap_or_default();
+        r.contents.artists = artists.remove(id).unwrap_or_default();
+        r.contents.genres = genres
+            .remove(id)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|f| c.genres.get(f.as_str()))
+            .collect();
+        r.contents.chapters = chapters.remove(id).unwrap_or_default();
+
+        out.push(r.contents);
+    }
+
+    Ok(out)
+}
@@ -1,43 +1,334 @@
-use mangaverse_entity::models::chapter::ChapterTable;
-use sqlx::{pool::PoolConnection, MySql, QueryBuilder};
+use std::collections::HashMap;
 
-use crate::Result;
+use itertools::Itertools;
+use mangaverse_entity::models::{chapter::ChapterTable, page::PageTable};
+use sqlx::{pool::PoolConnection, types::chrono::Utc, Acquire, MySql, QueryBuilder};
+use uuid::Uuid;
 
+use crate::parse::chapter::ChapterNumber;
+use crate::parse::date::{same_date, RelativeDates};
+use crate::{MSError, Result};
+
+use super::event::{
+    chapter_summary, log_chapters_removed, log_event, log_events, stored_chapter_summaries,
+    EventKind,
+};
+use super::guard::UpdateGuards;
+
+/// Most bind parameters MySQL accepts in a single prepared statement
+pub(crate) const MAX_PLACEHOLDERS: usize = 65_535;
+
+/// A single step needed to bring the stored chapters of a manga in line with a fresh scrape.
+///
+/// `ori` indexes into the stored chapters and `lat` into the scraped ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChapterOp {
+    /// Chapter is new on the source
+    Insert { lat: usize },
+    /// Same chapter, but its metadata or pages changed
+    Update { ori: usize, lat: usize },
+    /// Same chapter and contents, only its position changed
+    Move { ori: usize, lat: usize },
+    /// Same chapter, nothing changed
+    Keep { ori: usize, lat: usize },
+    /// Chapter is gone from the source
+    Delete { ori: usize },
+}
+
+pub(crate) fn pages_differ(ori: &ChapterTable, lat: &ChapterTable) -> bool {
+    ori.pages.len() != lat.pages.len()
+        || ori
+            .pages
+            .iter()
+            .zip(lat.pages.iter())
+            .any(|e| e.0.url != e.1.url)
+}
+
+/// Chapter numbers compare by their canonical key, so one stored before numbers were
+/// canonicalized does not count as changed. A `relative` date only changes once it drifts further
+/// than its unit.
+fn metadata_differs(ori: &ChapterTable, lat: &ChapterTable, relative: bool) -> bool {
+    ori.chapter_name != lat.chapter_name
+        || number_key(ori) != number_key(lat)
+        || !same_date(
+            ori.updated_at,
+            lat.updated_at,
+            relative,
+            Utc::now().naive_utc(),
+        )
+}
+
+/// Canonical chapter number, stored in `number_key` next to the site's own text. Chapters pair by
+/// it, so "Chapter 10.50" stored earlier still pairs with a scraped "10.5".
+fn number_key(ch: &ChapterTable) -> Option<String> {
+    let n = ch.chapter_number.trim();
+    if n.is_empty() {
+        None
+    } else {
+        Some(
+            ChapterNumber::parse(n)
+                .map(|f| f.to_string())
+                .unwrap_or_else(|| n.to_string()),
+        )
+    }
+}
+
+/// Matches stored chapters against freshly scraped ones.
+///
+/// Scraped chapters still carry their source url in `chapter_id`. Chapters are paired by source url
+/// first, then by chapter number when it is unambiguous on both sides. Stored chapters that predate
+/// `source_url` fall back to their position so they are not needlessly re-created.
+pub fn diff_chapters(
+    stored: &[ChapterTable],
+    stored_urls: &HashMap<String, String>,
+    latest: &[ChapterTable],
+    relative: &RelativeDates,
+) -> Vec<ChapterOp> {
+    let mut pair: Vec<Option<usize>> = vec![None; latest.len()];
+    let mut taken = vec![false; stored.len()];
+
+    let by_url: HashMap<&str, usize> = stored
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| stored_urls.get(&c.chapter_id).map(|u| (u.as_str(), i)))
+        .collect();
+
+    for (j, lat) in latest.iter().enumerate() {
+        if let Some(&i) = by_url.get(lat.chapter_id.as_str()) {
+            if !taken[i] {
+                taken[i] = true;
+                pair[j] = Some(i);
+            }
+        }
+    }
+
+    let mut ori_numbers: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, c) in stored.iter().enumerate().filter(|(i, _)| !taken[*i]) {
+        if let Some(n) = number_key(c) {
+            ori_numbers.entry(n).or_default().push(i);
+        }
+    }
+
+    let mut lat_numbers: HashMap<String, Vec<usize>> = HashMap::new();
+    for (j, c) in latest
+        .iter()
+        .enumerate()
+        .filter(|(j, _)| pair[*j].is_none())
+    {
+        if let Some(n) = number_key(c) {
+            lat_numbers.entry(n).or_default().push(j);
+        }
+    }
+
+    for (n, js) in &lat_numbers {
+        if let (Some([i]), [j]) = (ori_numbers.get(n).map(Vec::as_slice), js.as_slice()) {
+            taken[*i] = true;
+            pair[*j] = Some(*i);
+        }
+    }
+
+    let legacy: HashMap<i32, usize> = stored
+        .iter()
+        .enumerate()
+        .filter(|(i, c)| !taken[*i] && !stored_urls.contains_key(&c.chapter_id))
+        .map(|(i, c)| (c.sequence_number, i))
+        .collect();
+
+    for (j, lat) in latest.iter().enumerate() {
+        if pair[j].is_some() {
+            continue;
+        }
+        if let Some(&i) = legacy.get(&lat.sequence_number) {
+            if !taken[i] {
+                taken[i] = true;
+                pair[j] = Some(i);
+            }
+        }
+    }
+
+    let mut ops: Vec<ChapterOp> = taken
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| !**t)
+        .map(|(ori, _)| ChapterOp::Delete { ori })
+        .collect();
+
+    ops.extend(pair.into_iter().enumerate().map(|(lat, p)| match p {
+        None => ChapterOp::Insert { lat },
+        Some(ori) => {
+            let (o, l) = (&stored[ori], &latest[lat]);
+            let rel = relative.chapters.contains(&l.chapter_id);
+            if metadata_differs(o, l, rel) || pages_differ(o, l) {
+                ChapterOp::Update { ori, lat }
+            } else if o.sequence_number != l.sequence_number {
+                ChapterOp::Move { ori, lat }
+            } else {
+                ChapterOp::Keep { ori, lat }
+            }
+        }
+    }));
+
+    ops
+}
+
+/// Gives a scraped chapter its database identity and returns the source url it was scraped from.
+pub fn claim_chapter(ch: &mut ChapterTable, manga_id: &str) -> String {
+    ch.manga_id = manga_id.to_string();
+    std::mem::replace(&mut ch.chapter_id, Uuid::new_v4().to_string())
+}
+
+pub async fn get_chapter_urls(
+    manga_id: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<HashMap<String, String>> {
+    Ok(sqlx::query!(
+        "SELECT chapter_id, source_url from chapter where manga_id = ? and source_url is not null and removed_at is null",
+        manga_id
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .filter_map(|f| Some((f.chapter_id, f.source_url?)))
+    .collect())
+}
+
+pub async fn set_chapter_url(
+    chapter_id: &str,
+    source_url: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    sqlx::query!(
+        "UPDATE chapter SET source_url = ? where chapter_id = ?",
+        source_url,
+        chapter_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn move_chapter(
+    chapter_id: &str,
+    sequence_number: i32,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    sqlx::query!(
+        "UPDATE chapter SET sequence_number = ? where chapter_id = ?",
+        sequence_number,
+        chapter_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Writes a scraped chapter over its stored one. `relative` says whether its date was relative.
 pub async fn update_chapter(
     ori: &ChapterTable,
     lat: &ChapterTable,
+    relative: bool,
+    guards: &UpdateGuards,
     conn: &mut PoolConnection<MySql>,
 ) -> Result<()> {
-    let chk_met = ori.chapter_name == lat.chapter_name
-        && ori.chapter_number == lat.chapter_number
-        && ori.updated_at == lat.updated_at;
+    let chk_met =
+        !metadata_differs(ori, lat, relative) && ori.sequence_number == lat.sequence_number;
 
-    let chk_pg = ori
-        .pages
-        .iter()
-        .zip(lat.pages.iter())
-        .all(|e| e.0.url == e.1.url);
+    let chk_pg = !pages_differ(ori, lat) || !guards.allows_page_replacement(ori, lat);
+
+    if pages_differ(ori, lat) && !guards.allows_page_replacement(ori, lat) {
+        println!(
+            "Keeping {} pages of {}, the scrape found none",
+            ori.pages.len(),
+            ori.chapter_id
+        );
+    }
 
     if !chk_met {
-        sqlx::query!("UPDATE chapter SET chapter_name = ?, chapter_number = ?, updated_at = ? where chapter_id = ?", lat.chapter_name, lat.chapter_number, lat.updated_at, ori.chapter_id).execute(&mut *conn).await?;
+        //a relative date that merely drifted keeps the stored one
+        let now = Utc::now().naive_utc();
+        let updated_at = if same_date(ori.updated_at, lat.updated_at, relative, now) {
+            ori.updated_at
+        } else {
+            lat.updated_at
+        };
+        sqlx::query!("UPDATE chapter SET chapter_name = ?, chapter_number = ?, number_key = ?, updated_at = ?, sequence_number = ? where chapter_id = ?", lat.chapter_name, lat.chapter_number, number_key(lat), updated_at, lat.sequence_number, ori.chapter_id).execute(&mut *conn).await?;
     }
 
     if !chk_pg {
-        //remove previous...
+        //retire previous... they stay around as an older version of the chapter
         sqlx::query!(
-            "DELETE FROM chapter_page where chapter_id = ?",
+            "UPDATE chapter_page SET removed_at = ? where chapter_id = ? and removed_at is null",
+            Utc::now().timestamp_millis(),
             ori.chapter_id
         )
         .execute(&mut *conn)
         .await?;
 
         //add new
+        insert_pages(
+            lat.pages
+                .iter()
+                .map(|f| (ori.chapter_id.as_str(), f))
+                .collect::<Vec<_>>()
+                .as_slice(),
+            conn,
+        )
+        .await?;
+    }
+
+    if !chk_met || !chk_pg {
+        log_event(
+            ori.manga_id.as_str(),
+            EventKind::ChapterUpdated,
+            chapter_summary(ori).as_str(),
+            chapter_summary(lat).as_str(),
+            conn,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Marks chapters as removed. Their pages are left alone so the chapter can be restored whole.
+pub async fn delete_extra_chaps(chp_ids: &[&str], conn: &mut PoolConnection<MySql>) -> Result<()> {
+    if chp_ids.is_empty() {
+        return Ok(());
+    }
+
+    log_chapters_removed(chp_ids, conn).await?;
+
+    let now = Utc::now().timestamp_millis();
+
+    for chunk in chp_ids.chunks(MAX_PLACEHOLDERS - 1) {
+        let mut q = QueryBuilder::new("UPDATE chapter SET removed_at = ");
+        q.push_bind(now);
+        q.push(" where removed_at is null and chapter_id IN (");
+
+        let mut sep = q.separated(',');
+        for t in chunk {
+            sep.push_bind(*t);
+        }
+        q.push(')');
+
+        q.build().execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Inserts pages as `(chapter_id, page)` pairs, split so no statement goes over the placeholder limit
+pub async fn insert_pages(
+    pages: &[(&str, &PageTable)],
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    for chunk in pages.chunks(MAX_PLACEHOLDERS / 3) {
         let mut q = QueryBuilder::new("INSERT into chapter_page(url, page_number, chapter_id) ");
 
-        q.push_values(lat.pages.as_slice(), |mut b, page| {
+        q.push_values(chunk, |mut b, (chapter_id, page)| {
             b.push_bind(page.url.as_str());
             b.push_bind(page.page_number);
-            b.push_bind(ori.chapter_id.as_str());
+            b.push_bind(*chapter_id);
         });
 
         q.build().execute(&mut *conn).await?;
@@ -46,35 +337,316 @@ pub async fn update_chapter(
     Ok(())
 }
 
-pub async fn delete_extra_chaps(chp_ids: &[&str], conn: &mut PoolConnection<MySql>) -> Result<()> {
-    for t in chp_ids {
-        sqlx::query!("DELETE FROM chapter_page where chapter_id = ?", t)
-            .execute(&mut *conn)
-            .await?;
-        sqlx::query!("DELETE FROM chapter where chapter_id = ?", t)
-            .execute(&mut *conn)
-            .await?;
+#[derive(Debug, Clone)]
+pub struct RemovedChapter {
+    pub chapter_id: String,
+    pub chapter_name: String,
+    pub chapter_number: String,
+    pub sequence_number: i32,
+    pub source_url: Option<String>,
+    pub removed_at: i64,
+}
+
+pub async fn get_removed_chapters(
+    manga_id: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<RemovedChapter>> {
+    Ok(sqlx::query_as!(
+        RemovedChapter,
+        "SELECT chapter_id, chapter_name, chapter_number, sequence_number, source_url, removed_at as `removed_at!` from chapter where manga_id = ? and removed_at is not null order by removed_at DESC",
+        manga_id
+    )
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// Brings back a removed chapter. Refused when it is not removed, or when the source has since
+/// re-added it as a new live chapter, which restoring would duplicate.
+pub async fn restore_chapter(chapter_id: &str, conn: &mut PoolConnection<MySql>) -> Result<()> {
+    let mut txt = conn.begin().await?;
+
+    //MySQL can't read the updated table in a subquery, so the live duplicate is an anti-join
+    let restored = sqlx::query!(
+        "UPDATE chapter c LEFT JOIN chapter l ON l.manga_id = c.manga_id and l.source_url = c.source_url and l.removed_at is null SET c.removed_at = null where c.chapter_id = ? and c.removed_at is not null and l.chapter_id is null",
+        chapter_id
+    )
+    .execute(&mut txt)
+    .await?
+    .rows_affected();
+
+    if restored == 0 {
+        return Err(MSError {
+            message: format!(
+                "Chapter {} is not removed or was re-added by its source",
+                chapter_id
+            ),
+            err_type: crate::MSErrorType::OtherError,
+        });
+    }
+
+    for (manga_id, summary) in stored_chapter_summaries(&[chapter_id], &mut txt).await? {
+        log_event(
+            manga_id.as_str(),
+            EventKind::ChapterRestored,
+            "",
+            summary.as_str(),
+            &mut txt,
+        )
+        .await?;
     }
+
+    txt.commit().await?;
+
     Ok(())
 }
 
+/// A page set that was replaced by a later scrape.
+#[derive(Debug)]
+pub struct PageVersion {
+    pub removed_at: i64,
+    pub pages: Vec<PageTable>,
+}
+
+struct RemovedPage {
+    url: String,
+    page_number: i32,
+    removed_at: i64,
+}
+
+/// Lists the earlier page sets of a chapter, newest first.
+pub async fn get_previous_pages(
+    chapter_id: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<PageVersion>> {
+    let rows = sqlx::query_as!(
+        RemovedPage,
+        "SELECT url, page_number, removed_at as `removed_at!` from chapter_page where chapter_id = ? and removed_at is not null order by removed_at DESC, page_number ASC",
+        chapter_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .group_by(|f| f.removed_at)
+        .into_iter()
+        .map(|(removed_at, grp)| PageVersion {
+            removed_at,
+            pages: grp
+                .map(|f| PageTable {
+                    url: f.url,
+                    page_number: f.page_number,
+                    chapter_id: chapter_id.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Inserts chapters along with the source url each one was scraped from.
+///
+/// Chapters and their pages go in as multi-row inserts, so a first import of a long series
+/// takes a handful of statements rather than two per chapter.
 pub async fn add_extra_chaps(
-    chps: &[ChapterTable],
+    chps: &[(&str, &ChapterTable)],
     conn: &mut PoolConnection<MySql>,
 ) -> Result<()> {
-    for lat in chps {
-        sqlx::query!("INSERT INTO chapter(chapter_name, chapter_number, updated_at, chapter_id, manga_id, sequence_number, last_watch_time) VALUES(?, ?, ?, ?, ?, ?, ?)", lat.chapter_name, lat.chapter_number, lat.updated_at, lat.chapter_id, lat.manga_id, lat.sequence_number, lat.last_watch_time).execute(&mut *conn).await?;
-
-        let mut q = QueryBuilder::new("INSERT into chapter_page(url, page_number, chapter_id) ");
+    for chunk in chps.chunks(MAX_PLACEHOLDERS / 9) {
+        let mut q = QueryBuilder::new("INSERT INTO chapter(chapter_name, chapter_number, number_key, updated_at, chapter_id, manga_id, sequence_number, last_watch_time, source_url) ");
 
-        q.push_values(lat.pages.as_slice(), |mut b, page| {
-            b.push_bind(page.url.as_str());
-            b.push_bind(page.page_number);
+        q.push_values(chunk, |mut b, (source_url, lat)| {
+            b.push_bind(lat.chapter_name.as_str());
+            b.push_bind(lat.chapter_number.as_str());
+            b.push_bind(number_key(lat));
+            b.push_bind(lat.updated_at);
             b.push_bind(lat.chapter_id.as_str());
+            b.push_bind(lat.manga_id.as_str());
+            b.push_bind(lat.sequence_number);
+            b.push_bind(lat.last_watch_time);
+            b.push_bind(*source_url);
         });
 
         q.build().execute(&mut *conn).await?;
     }
 
-    Ok(())
+    insert_pages(
+        chps.iter()
+            .flat_map(|(_, lat)| lat.pages.iter().map(|f| (lat.chapter_id.as_str(), f)))
+            .collect::<Vec<_>>()
+            .as_slice(),
+        conn,
+    )
+    .await?;
+
+    log_events(
+        chps.iter()
+            .map(|(_, lat)| {
+                (
+                    lat.manga_id.as_str(),
+                    EventKind::ChapterAdded,
+                    String::new(),
+                    chapter_summary(lat),
+                )
+            })
+            .collect::<Vec<_>>()
+            .as_slice(),
+        conn,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::types::chrono::Duration;
+
+    use super::*;
+
+    fn ch(id: &str, number: &str, seq: i32) -> ChapterTable {
+        ChapterTable {
+            chapter_id: id.to_string(),
+            chapter_name: format!("Name {}", number),
+            chapter_number: number.to_string(),
+            sequence_number: seq,
+            pages: vec![PageTable {
+                url: format!("https://img.example.com/{}/1.jpg", number),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// Stored chapters with ids "s<n>" and source urls "u<n>"
+    fn stored(chapters: &[(&str, i32)]) -> (Vec<ChapterTable>, HashMap<String, String>) {
+        let chs: Vec<ChapterTable> = chapters
+            .iter()
+            .map(|(n, seq)| ch(format!("s{}", n).as_str(), n, *seq))
+            .collect();
+        let urls = chapters
+            .iter()
+            .map(|(n, _)| (format!("s{}", n), format!("u{}", n)))
+            .collect();
+        (chs, urls)
+    }
+
+    #[test]
+    fn matches_by_url_first() {
+        let (ori, urls) = stored(&[("1", 0), ("2", 1)]);
+        //the numbers were swapped on the site, the urls still say which chapter is which
+        let mut a = ch("u1", "2", 0);
+        a.chapter_name = "Name 1".to_string();
+        a.pages = ori[0].pages.clone();
+        let lat = vec![a, ch("u2", "2", 1)];
+
+        assert_eq!(
+            diff_chapters(&ori, &urls, &lat, &RelativeDates::default()),
+            vec![
+                ChapterOp::Update { ori: 0, lat: 0 },
+                ChapterOp::Keep { ori: 1, lat: 1 }
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_by_number_when_the_url_changed() {
+        let (ori, urls) = stored(&[("1", 0), ("2", 1)]);
+        let lat = vec![ch("new-1", "1", 0), ch("new-2", "2", 1)];
+
+        assert_eq!(
+            diff_chapters(&ori, &urls, &lat, &RelativeDates::default()),
+            vec![
+                ChapterOp::Keep { ori: 0, lat: 0 },
+                ChapterOp::Keep { ori: 1, lat: 1 }
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_ambiguous_numbers() {
+        let (ori, urls) = stored(&[("1", 0), ("1", 1)]);
+        let lat = vec![ch("new-1", "1", 0)];
+
+        assert_eq!(
+            diff_chapters(&ori, &urls, &lat, &RelativeDates::default()),
+            vec![
+                ChapterOp::Delete { ori: 0 },
+                ChapterOp::Delete { ori: 1 },
+                ChapterOp::Insert { lat: 0 }
+            ]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_sequence_for_chapters_without_url() {
+        let (ori, _) = stored(&[("1", 0), ("2", 1)]);
+        let mut lat = vec![ch("u1", "1", 0), ch("u2", "2", 1)];
+        //renumbered, so only the position pairs them
+        lat[0].chapter_number = "one".to_string();
+        lat[1].chapter_number = "two".to_string();
+
+        assert_eq!(
+            diff_chapters(&ori, &HashMap::new(), &lat, &RelativeDates::default()),
+            vec![
+                ChapterOp::Update { ori: 0, lat: 0 },
+                ChapterOp::Update { ori: 1, lat: 1 }
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_reorders_inserts_and_removals() {
+        let (ori, urls) = stored(&[("1", 0), ("2", 1), ("3", 2)]);
+        let lat = vec![ch("u2", "2", 0), ch("u1", "1", 1), ch("u4", "4", 2)];
+
+        assert_eq!(
+            diff_chapters(&ori, &urls, &lat, &RelativeDates::default()),
+            vec![
+                ChapterOp::Delete { ori: 2 },
+                ChapterOp::Move { ori: 1, lat: 0 },
+                ChapterOp::Move { ori: 0, lat: 1 },
+                ChapterOp::Insert { lat: 2 }
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_dates_only_change_once_they_drift_past_their_unit() {
+        let (mut ori, urls) = stored(&[("1", 0)]);
+        let now = Utc::now().naive_utc();
+        ori[0].updated_at = Some(now - Duration::hours(3));
+        let mut lat = vec![ch("u1", "1", 0)];
+        lat[0].updated_at = Some(now - Duration::minutes(150));
+
+        let mut relative = RelativeDates::default();
+        assert_eq!(
+            diff_chapters(&ori, &urls, &lat, &relative),
+            vec![ChapterOp::Update { ori: 0, lat: 0 }]
+        );
+
+        relative.chapters.insert("u1".to_string());
+        assert_eq!(
+            diff_chapters(&ori, &urls, &lat, &relative),
+            vec![ChapterOp::Keep { ori: 0, lat: 0 }]
+        );
+
+        lat[0].updated_at = Some(now - Duration::minutes(90));
+        assert_eq!(
+            diff_chapters(&ori, &urls, &lat, &relative),
+            vec![ChapterOp::Update { ori: 0, lat: 0 }]
+        );
+    }
+
+    #[test]
+    fn canonical_numbers_are_not_a_change() {
+        let (mut ori, urls) = stored(&[("1", 0)]);
+        ori[0].chapter_number = "10.50".to_string();
+        let mut lat = vec![ch("u1", "10.5", 0)];
+        lat[0].chapter_name = ori[0].chapter_name.clone();
+        lat[0].pages = ori[0].pages.clone();
+
+        assert_eq!(
+            diff_chapters(&ori, &urls, &lat, &RelativeDates::default()),
+            vec![ChapterOp::Keep { ori: 0, lat: 0 }]
+        );
+    }
 }
@@ -0,0 +1,111 @@
+use sqlx::{pool::PoolConnection, types::chrono::Utc, MySql};
+
+use crate::Result;
+
+/// Urls of a cached cover and its thumbnails
+#[derive(Debug, Clone, Default)]
+pub struct CachedCover {
+    pub original: String,
+    pub small: String,
+    pub medium: String,
+    pub large: String,
+}
+
+/// How a cover that failed for a reason that may pass, such as a timeout, is retried
+#[derive(Debug, Clone)]
+pub struct CoverRetry {
+    /// Failures in a row after which the cover is left alone until it changes
+    pub max_attempts: i32,
+    /// Millis before the first retry, doubled after each further failure
+    pub backoff: i64,
+}
+
+impl Default for CoverRetry {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            backoff: 15 * 60 * 1000,
+        }
+    }
+}
+
+/// Millis a newly stored `cover_url` is left to the refresh queued for it before
+/// `get_uncached_covers` picks it up, in case that refresh never finished
+pub const QUEUED_GRACE: i64 = 15 * 60 * 1000;
+
+/// A listed manga whose cover has not been cached for its current `cover_url`
+#[derive(Debug, Clone)]
+pub struct UncachedCover {
+    pub manga_id: String,
+    pub cover_url: String,
+    /// The manga's page, sent as the referer
+    pub url: String,
+}
+
+/// Covers not cached for their current `cover_url` that are due for a retry
+pub async fn get_uncached_covers(
+    limit: u32,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<UncachedCover>> {
+    Ok(sqlx::query_as!(
+        UncachedCover,
+        "SELECT manga_listing.manga_id, manga_listing.cover_url, manga.url from manga_listing, manga where manga_listing.manga_id = manga.manga_id and (manga_listing.cover_cached_from is null or manga_listing.cover_cached_from != manga_listing.cover_url) and manga_listing.cover_retry_at <= ? limit ?",
+        Utc::now().timestamp_millis(),
+        limit
+    )
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// Points the listing at the cached copies of the cover made from `cover_url`, unless the listing
+/// moved on to another cover meanwhile
+pub async fn set_listing_cover(
+    manga_id: &str,
+    cover_url: &str,
+    cover: &CachedCover,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    sqlx::query!(
+        "UPDATE manga_listing SET cover_cached_from = ?, cover_original = ?, cover_small = ?, cover_medium = ?, cover_large = ?, cover_error = null, cover_failed_from = null, cover_attempts = 0, cover_retry_at = null where manga_id = ? and cover_url = ?",
+        cover_url,
+        cover.original,
+        cover.small,
+        cover.medium,
+        cover.large,
+        manga_id,
+        cover_url
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Drops the cached copies of an older cover and records why `cover_url` could not be cached.
+/// With `retry` it is tried again after a backoff until it failed `max_attempts` times in a row,
+/// without it not until the cover changes. A listing that moved on to another cover is left alone.
+pub async fn set_listing_cover_failed(
+    manga_id: &str,
+    cover_url: &str,
+    error: &str,
+    retry: Option<&CoverRetry>,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    //assignments run left to right, so cover_retry_at sees the updated cover_attempts
+    sqlx::query!(
+        "UPDATE manga_listing SET cover_attempts = if(cover_failed_from <=> ?, cover_attempts + 1, 1), cover_failed_from = ?, cover_retry_at = if(? and cover_attempts < ?, ? + ? * pow(2, cover_attempts - 1), null), cover_cached_from = null, cover_original = null, cover_small = null, cover_medium = null, cover_large = null, cover_error = left(?, 1024) where manga_id = ? and cover_url = ?",
+        cover_url,
+        cover_url,
+        retry.is_some(),
+        retry.map_or(0, |f| f.max_attempts),
+        Utc::now().timestamp_millis(),
+        retry.map_or(0, |f| f.backoff),
+        error,
+        manga_id,
+        cover_url
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
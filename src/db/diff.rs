@@ -0,0 +1,430 @@
+use std::collections::HashSet;
+
+use itertools::Itertools;
+use mangaverse_entity::models::{chapter::ChapterTable, manga::MangaTable};
+use serde::Serialize;
+
+use super::chapter::{pages_differ, ChapterOp};
+use super::guard::UpdateGuards;
+
+/// What a refresh changed, or would change when run as a dry run.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct MangaDiff {
+    pub manga_id: String,
+    pub fields: Vec<FieldChange>,
+    pub genres_added: Vec<String>,
+    pub genres_removed: Vec<String>,
+    pub authors_added: Vec<String>,
+    pub authors_removed: Vec<String>,
+    pub artists_added: Vec<String>,
+    pub artists_removed: Vec<String>,
+    pub titles_added: Vec<String>,
+    pub titles_removed: Vec<String>,
+    pub chapters_added: Vec<ChapterSummary>,
+    pub chapters_updated: Vec<ChapterSummary>,
+    pub chapters_moved: Vec<ChapterSummary>,
+    pub chapters_removed: Vec<ChapterSummary>,
+    pub page_sets: Vec<PageSetChange>,
+    /// Changes an update guard refused to write, with the reason
+    pub held_back: Vec<String>,
+    /// Set when the scrape was quarantined instead of written
+    pub quarantined: Option<String>,
+    /// Set when nothing was looked at because an earlier scrape is still awaiting review
+    pub skipped: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChapterSummary {
+    /// Stored chapter id, or the source url for chapters that are not stored yet
+    pub chapter_id: String,
+    pub chapter_name: String,
+    pub chapter_number: String,
+    pub sequence_number: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PageSetChange {
+    pub chapter_id: String,
+    pub pages_before: usize,
+    pub pages_after: usize,
+}
+
+impl From<&ChapterTable> for ChapterSummary {
+    fn from(a: &ChapterTable) -> Self {
+        Self {
+            chapter_id: a.chapter_id.clone(),
+            chapter_name: a.chapter_name.clone(),
+            chapter_number: a.chapter_number.clone(),
+            sequence_number: a.sequence_number,
+        }
+    }
+}
+
+impl MangaDiff {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+            && self.genres_added.is_empty()
+            && self.genres_removed.is_empty()
+            && self.authors_added.is_empty()
+            && self.authors_removed.is_empty()
+            && self.artists_added.is_empty()
+            && self.artists_removed.is_empty()
+            && self.titles_added.is_empty()
+            && self.titles_removed.is_empty()
+            && self.chapters_added.is_empty()
+            && self.chapters_updated.is_empty()
+            && self.chapters_moved.is_empty()
+            && self.chapters_removed.is_empty()
+            && self.page_sets.is_empty()
+    }
+
+    pub fn has_field(&self, field: &str) -> bool {
+        self.fields.iter().any(|f| f.field == field)
+    }
+
+    pub fn genres_changed(&self) -> bool {
+        !self.genres_added.is_empty() || !self.genres_removed.is_empty()
+    }
+
+    /// Whether anything shown in `manga_listing` changed
+    pub fn listing_changed(&self) -> bool {
+        self.genres_changed()
+            || self.has_field("name")
+            || self.has_field("cover_url")
+            || self.has_field("description")
+            || self.has_field("status")
+    }
+}
+
+fn push_field(out: &mut Vec<FieldChange>, field: &'static str, before: String, after: String) {
+    if before != after {
+        out.push(FieldChange {
+            field,
+            before,
+            after,
+        });
+    }
+}
+
+/// Splits two lists into what was added to and removed from `before`, keeping list order
+fn set_changes<'a>(
+    before: impl Iterator<Item = &'a str> + Clone,
+    after: impl Iterator<Item = &'a str> + Clone,
+) -> (Vec<String>, Vec<String>) {
+    let b: HashSet<&str> = before.clone().collect();
+    let a: HashSet<&str> = after.clone().collect();
+
+    (
+        after
+            .filter(|f| !b.contains(f))
+            .unique()
+            .map(ToString::to_string)
+            .collect(),
+        before
+            .filter(|f| !a.contains(f))
+            .unique()
+            .map(ToString::to_string)
+            .collect(),
+    )
+}
+
+fn fmt_date<T: ToString>(t: &Option<T>) -> String {
+    t.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+/// Builds the diff between a stored manga and a fresh scrape from already planned chapter ops.
+///
+/// Titles are compared against the ones the stored manga contributed itself, since its `titles`
+/// hold the whole group's.
+pub fn diff_manga(
+    stored: &MangaTable<'_>,
+    mng: &MangaTable<'_>,
+    claimed_titles: &[String],
+    ops: &[ChapterOp],
+    guards: &UpdateGuards,
+) -> MangaDiff {
+    let mut d = MangaDiff {
+        manga_id: stored.id.clone(),
+        ..Default::default()
+    };
+
+    push_field(&mut d.fields, "name", stored.name.clone(), mng.name.clone());
+    push_field(
+        &mut d.fields,
+        "cover_url",
+        stored.cover_url.clone(),
+        mng.cover_url.clone(),
+    );
+    push_field(
+        &mut d.fields,
+        "description",
+        stored.description.clone(),
+        mng.description.clone(),
+    );
+    push_field(
+        &mut d.fields,
+        "last_updated",
+        fmt_date(&stored.last_updated),
+        fmt_date(&mng.last_updated),
+    );
+    push_field(
+        &mut d.fields,
+        "status",
+        stored.status.clone(),
+        mng.status.clone(),
+    );
+
+    (d.genres_added, d.genres_removed) = set_changes(
+        stored.genres.iter().map(|f| f.name.as_str()),
+        mng.genres.iter().map(|f| f.name.as_str()),
+    );
+
+    (d.authors_added, d.authors_removed) = set_changes(
+        stored.authors.iter().map(String::as_str),
+        mng.authors.iter().map(String::as_str),
+    );
+
+    (d.artists_added, d.artists_removed) = set_changes(
+        stored.artists.iter().map(String::as_str),
+        mng.artists.iter().map(String::as_str),
+    );
+
+    (d.titles_added, d.titles_removed) = set_changes(
+        claimed_titles.iter().map(String::as_str),
+        mng.titles.iter().map(String::as_str),
+    );
+
+    for op in ops {
+        match *op {
+            ChapterOp::Insert { lat } => d.chapters_added.push((&mng.chapters[lat]).into()),
+            ChapterOp::Delete { ori } => d.chapters_removed.push((&stored.chapters[ori]).into()),
+            ChapterOp::Move { ori, lat } => {
+                let mut s: ChapterSummary = (&stored.chapters[ori]).into();
+                s.sequence_number = mng.chapters[lat].sequence_number;
+                d.chapters_moved.push(s);
+            }
+            ChapterOp::Update { ori, lat } => {
+                let (o, l) = (&stored.chapters[ori], &mng.chapters[lat]);
+                let mut s: ChapterSummary = l.into();
+                s.chapter_id = o.chapter_id.clone();
+                d.chapters_updated.push(s);
+
+                if pages_differ(o, l) {
+                    if guards.allows_page_replacement(o, l) {
+                        d.page_sets.push(PageSetChange {
+                            chapter_id: o.chapter_id.clone(),
+                            pages_before: o.pages.len(),
+                            pages_after: l.pages.len(),
+                        });
+                    } else {
+                        d.held_back.push(format!(
+                            "Kept {} pages of chapter {}, the scrape found none",
+                            o.pages.len(),
+                            o.chapter_id
+                        ));
+                    }
+                }
+            }
+            ChapterOp::Keep { .. } => {}
+        }
+    }
+
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use mangaverse_entity::models::{genre::Genre, page::PageTable, source::SourceTable};
+
+    use super::*;
+
+    static SOURCE: SourceTable = SourceTable {
+        id: String::new(),
+        name: String::new(),
+        priority: 0,
+    };
+
+    fn manga<'a>(genres: Vec<&'a Genre>, chapters: Vec<ChapterTable>) -> MangaTable<'a> {
+        MangaTable {
+            id: "m1".to_string(),
+            linked_id: "l1".to_string(),
+            is_listed: true,
+            name: "Solo Leveling".to_string(),
+            cover_url: "https://img.example.com/cover.jpg".to_string(),
+            url: "https://example.com/solo-leveling".to_string(),
+            last_updated: None,
+            status: "Ongoing".to_string(),
+            is_main: true,
+            description: "A hunter".to_string(),
+            last_watch_time: None,
+            public_id: "p1".to_string(),
+            is_old: false,
+            source: &SOURCE,
+            chapters,
+            authors: vec!["Chugong".to_string()],
+            artists: vec!["Dubu".to_string()],
+            genres,
+            titles: vec!["Solo Leveling".to_string()],
+        }
+    }
+
+    fn ch(id: &str, seq: i32, pages: usize) -> ChapterTable {
+        ChapterTable {
+            chapter_id: id.to_string(),
+            chapter_name: format!("Name {}", seq),
+            chapter_number: (seq + 1).to_string(),
+            sequence_number: seq,
+            pages: (0..pages)
+                .map(|i| PageTable {
+                    url: format!("https://img.example.com/{}/{}.jpg", id, i),
+                    page_number: i as i32,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn genre(name: &str) -> Genre {
+        Genre {
+            id: name.to_lowercase(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn unchanged_manga_gives_an_empty_diff() {
+        let action = genre("Action");
+        let stored = manga(vec![&action], vec![ch("s1", 0, 2)]);
+        let mng = manga(vec![&action], vec![ch("u1", 0, 2)]);
+
+        let d = diff_manga(
+            &stored,
+            &mng,
+            &stored.titles,
+            &[ChapterOp::Keep { ori: 0, lat: 0 }],
+            &UpdateGuards::default(),
+        );
+
+        assert!(d.is_empty());
+        assert!(!d.listing_changed());
+        assert_eq!(d.manga_id, "m1");
+    }
+
+    #[test]
+    fn reports_changed_fields() {
+        let stored = manga(vec![], vec![]);
+        let mut mng = manga(vec![], vec![]);
+        mng.cover_url = "https://img.example.com/cover2.jpg".to_string();
+        mng.status = "Completed".to_string();
+
+        let d = diff_manga(&stored, &mng, &stored.titles, &[], &UpdateGuards::default());
+
+        assert_eq!(
+            d.fields,
+            vec![
+                FieldChange {
+                    field: "cover_url",
+                    before: stored.cover_url.clone(),
+                    after: mng.cover_url.clone(),
+                },
+                FieldChange {
+                    field: "status",
+                    before: "Ongoing".to_string(),
+                    after: "Completed".to_string(),
+                },
+            ]
+        );
+        assert!(d.has_field("cover_url"));
+        assert!(!d.has_field("name"));
+        assert!(d.listing_changed());
+    }
+
+    #[test]
+    fn reports_added_and_removed_lists() {
+        let (action, drama) = (genre("Action"), genre("Drama"));
+        let stored = manga(vec![&action], vec![]);
+        let mut mng = manga(vec![&action, &drama], vec![]);
+        mng.authors = vec!["Chu-Gong".to_string()];
+        mng.titles.push("Na Honjaman Level Up".to_string());
+
+        //the group also lists a title another manga contributed, which is not this one's to remove
+        let claimed = vec!["Solo Leveling".to_string()];
+
+        let d = diff_manga(&stored, &mng, &claimed, &[], &UpdateGuards::default());
+
+        assert_eq!(d.genres_added, vec!["Drama"]);
+        assert!(d.genres_removed.is_empty());
+        assert!(d.genres_changed());
+        assert_eq!(d.authors_added, vec!["Chu-Gong"]);
+        assert_eq!(d.authors_removed, vec!["Chugong"]);
+        assert!(d.artists_added.is_empty() && d.artists_removed.is_empty());
+        assert_eq!(d.titles_added, vec!["Na Honjaman Level Up"]);
+        assert!(d.titles_removed.is_empty());
+    }
+
+    #[test]
+    fn reports_chapter_changes() {
+        let stored = manga(
+            vec![],
+            vec![
+                ch("s1", 0, 2),
+                ch("s2", 1, 2),
+                ch("s3", 2, 2),
+                ch("s4", 3, 3),
+            ],
+        );
+        let mut emptied = ch("u4", 3, 0);
+        emptied.chapter_name = "Renamed".to_string();
+        let mng = manga(
+            vec![],
+            vec![ch("u2", 0, 2), ch("u3", 1, 4), ch("u5", 2, 1), emptied],
+        );
+
+        let ops = [
+            ChapterOp::Delete { ori: 0 },
+            ChapterOp::Move { ori: 1, lat: 0 },
+            ChapterOp::Update { ori: 2, lat: 1 },
+            ChapterOp::Insert { lat: 2 },
+            ChapterOp::Update { ori: 3, lat: 3 },
+        ];
+
+        let d = diff_manga(
+            &stored,
+            &mng,
+            &stored.titles,
+            &ops,
+            &UpdateGuards::default(),
+        );
+
+        let ids = |v: &[ChapterSummary]| v.iter().map(|f| f.chapter_id.clone()).collect_vec();
+
+        assert_eq!(ids(&d.chapters_removed), vec!["s1"]);
+        assert_eq!(ids(&d.chapters_added), vec!["u5"]);
+        assert_eq!(ids(&d.chapters_moved), vec!["s2"]);
+        assert_eq!(d.chapters_moved[0].sequence_number, 0);
+        //updates keep the stored id but show the scraped metadata
+        assert_eq!(ids(&d.chapters_updated), vec!["s3", "s4"]);
+        assert_eq!(d.chapters_updated[1].chapter_name, "Renamed");
+        assert_eq!(
+            d.page_sets,
+            vec![PageSetChange {
+                chapter_id: "s3".to_string(),
+                pages_before: 2,
+                pages_after: 4,
+            }]
+        );
+        assert_eq!(
+            d.held_back,
+            vec!["Kept 3 pages of chapter s4, the scrape found none"]
+        );
+        assert!(!d.is_empty());
+    }
+}
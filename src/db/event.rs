@@ -0,0 +1,222 @@
+use std::fmt::Display;
+
+use mangaverse_entity::models::chapter::ChapterTable;
+use sqlx::{pool::PoolConnection, types::chrono::Utc, MySql, MySqlConnection, QueryBuilder, Row};
+use uuid::Uuid;
+
+use crate::Result;
+
+use super::chapter::MAX_PLACEHOLDERS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    MangaInserted,
+    MetadataUpdated,
+    GenresUpdated,
+    AuthorsUpdated,
+    ArtistsUpdated,
+    TitlesUpdated,
+    ChapterAdded,
+    ChapterUpdated,
+    ChapterRemoved,
+    ChapterRestored,
+    /// Moved into another group, the summaries hold the `linked_id`s
+    MangaLinked,
+    /// Split out into a group of its own
+    MangaUnlinked,
+    /// Moved along with the rest of its group into another one
+    GroupsMerged,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::MangaInserted => "manga_inserted",
+            EventKind::MetadataUpdated => "metadata_updated",
+            EventKind::GenresUpdated => "genres_updated",
+            EventKind::AuthorsUpdated => "authors_updated",
+            EventKind::ArtistsUpdated => "artists_updated",
+            EventKind::TitlesUpdated => "titles_updated",
+            EventKind::ChapterAdded => "chapter_added",
+            EventKind::ChapterUpdated => "chapter_updated",
+            EventKind::ChapterRemoved => "chapter_removed",
+            EventKind::ChapterRestored => "chapter_restored",
+            EventKind::MangaLinked => "manga_linked",
+            EventKind::MangaUnlinked => "manga_unlinked",
+            EventKind::GroupsMerged => "groups_merged",
+        }
+    }
+}
+
+impl Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangeEvent {
+    pub event_id: String,
+    pub manga_id: String,
+    pub source_id: Option<String>,
+    pub kind: String,
+    pub before_summary: String,
+    pub after_summary: String,
+    pub created_at: i64,
+}
+
+/// One line description of a chapter for the event log
+pub fn chapter_summary(ch: &ChapterTable) -> String {
+    summary_line(
+        ch.sequence_number,
+        ch.chapter_number.as_str(),
+        ch.chapter_name.as_str(),
+        ch.pages.len(),
+    )
+}
+
+fn summary_line(sequence_number: i32, number: &str, name: &str, pages: usize) -> String {
+    format!("#{} {} {} ({} pages)", sequence_number, number, name, pages)
+}
+
+/// Describes stored chapters the way `chapter_summary` does, as `(manga_id, summary)` pairs
+pub(crate) async fn stored_chapter_summaries(
+    chapter_ids: &[&str],
+    conn: &mut MySqlConnection,
+) -> Result<Vec<(String, String)>> {
+    let mut out = Vec::with_capacity(chapter_ids.len());
+
+    for chunk in chapter_ids.chunks(MAX_PLACEHOLDERS) {
+        let mut q = QueryBuilder::new("SELECT chapter.manga_id, chapter.sequence_number, chapter.chapter_number, chapter.chapter_name, (SELECT count(*) from chapter_page where chapter_page.chapter_id = chapter.chapter_id and chapter_page.removed_at is null) as pages from chapter where chapter.chapter_id IN (");
+
+        let mut sep = q.separated(',');
+        for t in chunk {
+            sep.push_bind(*t);
+        }
+        q.push(") order by chapter.sequence_number ASC");
+
+        for r in q.build().fetch_all(&mut *conn).await? {
+            let pages: i64 = r.try_get("pages")?;
+            out.push((
+                r.try_get("manga_id")?,
+                summary_line(
+                    r.try_get("sequence_number")?,
+                    r.try_get("chapter_number")?,
+                    r.try_get("chapter_name")?,
+                    pages as usize,
+                ),
+            ));
+        }
+    }
+
+    Ok(out)
+}
+
+/// Appends an event for the manga. The source is taken from the manga row itself.
+pub async fn log_event(
+    manga_id: &str,
+    kind: EventKind,
+    before: &str,
+    after: &str,
+    conn: &mut MySqlConnection,
+) -> Result<()> {
+    sqlx::query!(
+        "INSERT INTO manga_event(event_id, manga_id, source_id, kind, before_summary, after_summary, created_at) SELECT ?, manga_id, source_id, ?, ?, ?, ? from manga where manga_id = ?",
+        Uuid::new_v4().to_string(),
+        kind.as_str(),
+        before,
+        after,
+        Utc::now().timestamp_millis(),
+        manga_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Appends many events in as few statements as the placeholder limit allows.
+/// Each entry is `(manga_id, kind, before, after)`.
+pub async fn log_events(
+    events: &[(&str, EventKind, String, String)],
+    conn: &mut MySqlConnection,
+) -> Result<()> {
+    let now = Utc::now().timestamp_millis();
+
+    for chunk in events.chunks(MAX_PLACEHOLDERS / 6) {
+        let mut q = QueryBuilder::new("INSERT INTO manga_event(event_id, manga_id, source_id, kind, before_summary, after_summary, created_at) SELECT e.event_id, e.manga_id, manga.source_id, e.kind, e.before_summary, e.after_summary, e.created_at from manga, (");
+
+        for (i, (manga_id, kind, before, after)) in chunk.iter().enumerate() {
+            if i > 0 {
+                q.push(" UNION ALL ");
+            }
+            q.push("SELECT ");
+            let mut sep = q.separated(", ");
+            sep.push_bind(Uuid::new_v4().to_string())
+                .push_unseparated(" as event_id");
+            sep.push_bind(*manga_id).push_unseparated(" as manga_id");
+            sep.push_bind(kind.as_str()).push_unseparated(" as kind");
+            sep.push_bind(before.as_str())
+                .push_unseparated(" as before_summary");
+            sep.push_bind(after.as_str())
+                .push_unseparated(" as after_summary");
+            sep.push_bind(now).push_unseparated(" as created_at");
+        }
+
+        q.push(") e where manga.manga_id = e.manga_id");
+
+        q.build().execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Logs the removal of stored chapters, described like `chapter_summary` does from their rows
+pub async fn log_chapters_removed(chapter_ids: &[&str], conn: &mut MySqlConnection) -> Result<()> {
+    let summaries = stored_chapter_summaries(chapter_ids, conn).await?;
+
+    log_events(
+        summaries
+            .iter()
+            .map(|(manga_id, summary)| {
+                (
+                    manga_id.as_str(),
+                    EventKind::ChapterRemoved,
+                    summary.clone(),
+                    String::new(),
+                )
+            })
+            .collect::<Vec<_>>()
+            .as_slice(),
+        conn,
+    )
+    .await
+}
+
+pub async fn get_events_for_manga(
+    manga_id: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<ChangeEvent>> {
+    Ok(sqlx::query_as!(
+        ChangeEvent,
+        "SELECT event_id, manga_id, source_id, kind, before_summary, after_summary, created_at from manga_event where manga_id = ? order by created_at ASC",
+        manga_id
+    )
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// Events with `from <= created_at < to`, both in epoch millis
+pub async fn get_events_between(
+    from: i64,
+    to: i64,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<ChangeEvent>> {
+    Ok(sqlx::query_as!(
+        ChangeEvent,
+        "SELECT event_id, manga_id, source_id, kind, before_summary, after_summary, created_at from manga_event where created_at >= ? and created_at < ? order by created_at ASC",
+        from,
+        to
+    )
+    .fetch_all(&mut *conn)
+    .await?)
+}
@@ -1,26 +1,110 @@
 use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
 
-use mangaverse_entity::models::genre::Genre;
-use sqlx::{pool::PoolConnection, MySql, QueryBuilder};
+use lazy_static::lazy_static;
+use mangaverse_entity::models::{genre::Genre, source::SourceTable};
+use sqlx::{pool::PoolConnection, types::chrono::Utc, MySql, QueryBuilder};
 use uuid::Uuid;
 
 use crate::Result;
 
+lazy_static! {
+    /// Spellings that different sites use for the same genre, already run through `clean_genre`
+    static ref CANONICAL: HashMap<&'static str, &'static str> = HashMap::from([
+        ("sci fi", "science fiction"),
+        ("scifi", "science fiction"),
+        ("shonen", "shounen"),
+        ("shojo", "shoujo"),
+        ("shonen ai", "shounen ai"),
+        ("shojo ai", "shoujo ai"),
+        ("gender swap", "gender bender"),
+        ("genderswap", "gender bender"),
+        ("martial art", "martial arts"),
+        ("oneshot", "one shot"),
+        ("webtoons", "webtoon"),
+        ("boys love", "yaoi"),
+        ("girls love", "yuri"),
+    ]);
+}
+
+/// Lowercases and trims a genre and turns `-`, `_` and runs of whitespace into single spaces
+fn clean_genre(raw: &str) -> String {
+    raw.split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+        .filter(|f| !f.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// The taxonomy name a raw genre from any source is stored under
+pub fn canonical_genre(raw: &str) -> String {
+    let c = clean_genre(raw);
+    match CANONICAL.get(c.as_str()) {
+        Some(f) => f.to_string(),
+        None => c,
+    }
+}
+
+/// Resolves the genre names one source uses to canonical genres.
+///
+/// Names that resolve to nothing are remembered so they can be written to the unknown-genre report
+/// with `record_unknown_genres`.
+#[derive(Debug, Default)]
+pub struct GenreMap {
+    pub source_id: String,
+    aliases: HashMap<String, Genre>,
+    unknown: Mutex<HashMap<String, u32>>,
+}
+
+impl GenreMap {
+    pub fn get(&self, raw: &str) -> Option<&Genre> {
+        let r = self
+            .aliases
+            .get(raw.trim().to_lowercase().as_str())
+            .or_else(|| self.aliases.get(canonical_genre(raw).as_str()));
+
+        if r.is_none() && !raw.trim().is_empty() {
+            if let Ok(mut u) = self.unknown.lock() {
+                *u.entry(raw.trim().to_lowercase()).or_default() += 1;
+            }
+        }
+
+        r
+    }
+
+    /// Hands over the unresolved names seen so far along with how often each was seen
+    pub fn take_unknown(&self) -> HashMap<String, u32> {
+        self.unknown
+            .lock()
+            .map(|mut f| std::mem::take(&mut *f))
+            .unwrap_or_default()
+    }
+}
+
+/// Inserts the canonical genres for `set` and returns all stored genres by canonical name in `out`
 pub async fn insert_genre(
     set: &HashSet<String>,
     conn: &mut PoolConnection<MySql>,
     out: &mut HashMap<String, Genre>,
 ) -> Result<()> {
-    let mut q = QueryBuilder::new("INSERT into genre(genre_id, name) ");
+    let names: HashSet<String> = set
+        .iter()
+        .map(|f| canonical_genre(f))
+        .filter(|f| !f.is_empty())
+        .collect();
 
-    q.push_values(set, |mut b, genre| {
-        b.push_bind(Uuid::new_v4().to_string());
-        b.push_bind(genre);
-    });
+    if !names.is_empty() {
+        let mut q = QueryBuilder::new("INSERT into genre(genre_id, name) ");
 
-    q.push(" ON DUPLICATE KEY update genre_id = genre_id");
+        q.push_values(&names, |mut b, genre| {
+            b.push_bind(Uuid::new_v4().to_string());
+            b.push_bind(genre);
+        });
 
-    q.build().execute(&mut *conn).await?;
+        q.push(" ON DUPLICATE KEY update genre_id = genre_id");
+
+        q.build().execute(&mut *conn).await?;
+    }
 
     let all = sqlx::query_as!(
         Genre,
@@ -32,3 +116,324 @@ pub async fn insert_genre(
     out.extend(all.into_iter().map(|f| (f.name.to_string(), f)));
     Ok(())
 }
+
+/// Stores the genre list a source offers as aliases of canonical genres and returns its map.
+/// `genres` maps each genre name to the page listing it, as the source's `get_*_genres` return it.
+///
+/// Aliases that already exist keep their genre, so manual fixes made with `set_genre_alias` stick.
+pub async fn insert_source_genres(
+    src: &SourceTable,
+    genres: &HashMap<String, Option<String>>,
+    conn: &mut PoolConnection<MySql>,
+    out: &mut HashMap<String, Genre>,
+) -> Result<GenreMap> {
+    insert_genre(&genres.keys().cloned().collect(), conn, out).await?;
+
+    let rows = genres
+        .keys()
+        .filter_map(|f| {
+            let alias = f.trim().to_lowercase();
+            out.get(canonical_genre(f).as_str())
+                .map(|g| (alias, g.id.as_str()))
+        })
+        .collect::<Vec<_>>();
+
+    if !rows.is_empty() {
+        let mut q = QueryBuilder::new("INSERT into genre_alias(source_id, alias, genre_id) ");
+
+        q.push_values(&rows, |mut b, (alias, genre_id)| {
+            b.push_bind(src.id.as_str());
+            b.push_bind(alias.as_str());
+            b.push_bind(*genre_id);
+        });
+
+        q.push(" ON DUPLICATE KEY update genre_id = genre_alias.genre_id");
+
+        q.build().execute(&mut *conn).await?;
+    }
+
+    let gone = sync_source_genres(src, genres, conn).await?;
+    if !gone.is_empty() {
+        println!("Genres gone from {}: {}", src.name, gone.join(", "));
+    }
+
+    load_genre_map(src, conn).await
+}
+
+/// Records the genres a source currently offers and marks the ones it no longer lists as removed.
+/// Returns the names of the genres that disappeared in this run.
+///
+/// An empty list is taken as a broken scrape and leaves everything as it was.
+pub async fn sync_source_genres(
+    src: &SourceTable,
+    genres: &HashMap<String, Option<String>>,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<String>> {
+    if genres.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let now = Utc::now().timestamp_millis();
+
+    for (name, browse_url) in genres {
+        let alias = name.trim().to_lowercase();
+        sqlx::query!(
+            "INSERT into source_genre(source_id, name, genre_id, browse_url, first_seen, last_seen) SELECT ?, ?, genre_id, ?, ?, ? from genre_alias where source_id = ? and alias = ? ON DUPLICATE KEY update genre_id = VALUES(genre_id), browse_url = VALUES(browse_url), last_seen = VALUES(last_seen), removed_at = null",
+            src.id.as_str(),
+            alias,
+            browse_url.as_deref(),
+            now,
+            now,
+            src.id.as_str(),
+            alias
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    let gone = sqlx::query!(
+        "SELECT name from source_genre where source_id = ? and removed_at is null and last_seen < ?",
+        src.id.as_str(),
+        now
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|f| f.name)
+    .collect::<Vec<_>>();
+
+    sqlx::query!(
+        "UPDATE source_genre SET removed_at = ? where source_id = ? and removed_at is null and last_seen < ?",
+        now,
+        src.id.as_str(),
+        now
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(gone)
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceGenre {
+    pub source_id: String,
+    pub source_name: String,
+    /// The genre as the source names it
+    pub name: String,
+    pub browse_url: Option<String>,
+}
+
+/// Enabled sources that still list the genre, best priority first. `genre` may be any spelling.
+pub async fn get_sources_for_genre(
+    genre: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<SourceGenre>> {
+    Ok(sqlx::query_as!(
+        SourceGenre,
+        "SELECT source.source_id, source.name as source_name, source_genre.name, source_genre.browse_url from source_genre, source, genre where source_genre.source_id = source.source_id and source_genre.genre_id = genre.genre_id and genre.name = ? and source_genre.removed_at is null and source.is_enabled order by source.priority ASC",
+        canonical_genre(genre)
+    )
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+#[derive(Debug, Clone)]
+pub struct RemovedSourceGenre {
+    pub source_id: String,
+    pub name: String,
+    pub removed_at: i64,
+}
+
+/// Genres sources used to offer but no longer list, most recently removed first
+pub async fn get_removed_source_genres(
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<RemovedSourceGenre>> {
+    Ok(sqlx::query_as!(
+        RemovedSourceGenre,
+        "SELECT source_id, name, removed_at as `removed_at!` from source_genre where removed_at is not null order by removed_at DESC"
+    )
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+pub async fn load_genre_map(
+    src: &SourceTable,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<GenreMap> {
+    let rows = sqlx::query!(
+        "SELECT genre_alias.alias, genre.genre_id, genre.name from genre_alias, genre where genre_alias.genre_id = genre.genre_id and genre_alias.source_id = ?",
+        src.id.as_str()
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut aliases = HashMap::new();
+
+    for f in rows {
+        //canonical names resolve too, for manga pages that spell a genre differently than the list
+        aliases.entry(f.name.clone()).or_insert_with(|| Genre {
+            id: f.genre_id.clone(),
+            name: f.name.clone(),
+        });
+        aliases.insert(
+            f.alias,
+            Genre {
+                id: f.genre_id,
+                name: f.name,
+            },
+        );
+    }
+
+    Ok(GenreMap {
+        source_id: src.id.clone(),
+        aliases,
+        unknown: Mutex::default(),
+    })
+}
+
+/// Points a source's genre name at a canonical genre, creating the genre if needed, and clears
+/// it from the unknown-genre report.
+pub async fn set_genre_alias(
+    source_id: &str,
+    alias: &str,
+    genre_name: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    let alias = alias.trim().to_lowercase();
+    let name = canonical_genre(genre_name);
+
+    sqlx::query!(
+        "INSERT into genre(genre_id, name) VALUES(?, ?) ON DUPLICATE KEY update genre_id = genre_id",
+        Uuid::new_v4().to_string(),
+        name
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "INSERT into genre_alias(source_id, alias, genre_id) SELECT ?, ?, genre_id from genre where name = ? ON DUPLICATE KEY update genre_id = VALUES(genre_id)",
+        source_id,
+        alias,
+        name
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE source_genre SET genre_id = (SELECT genre_id from genre where name = ?) where source_id = ? and name = ?",
+        name,
+        source_id,
+        alias
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "DELETE from unknown_genre where source_id = ? and name = ?",
+        source_id,
+        alias
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Writes the names the map could not resolve into the unknown-genre report
+pub async fn record_unknown_genres(map: &GenreMap, conn: &mut PoolConnection<MySql>) -> Result<()> {
+    let now = Utc::now().timestamp_millis();
+
+    for (name, n) in map.take_unknown() {
+        sqlx::query!(
+            "INSERT into unknown_genre(source_id, name, occurrences, first_seen, last_seen) VALUES(?, ?, ?, ?, ?) ON DUPLICATE KEY update occurrences = occurrences + VALUES(occurrences), last_seen = VALUES(last_seen)",
+            map.source_id.as_str(),
+            name,
+            n,
+            now,
+            now
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct UnknownGenre {
+    pub source_id: String,
+    pub source_name: String,
+    pub name: String,
+    pub occurrences: i32,
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+/// Unresolved genre names across all sources, most frequent first
+pub async fn get_unknown_genres(conn: &mut PoolConnection<MySql>) -> Result<Vec<UnknownGenre>> {
+    Ok(sqlx::query_as!(
+        UnknownGenre,
+        "SELECT unknown_genre.source_id, source.name as source_name, unknown_genre.name, unknown_genre.occurrences, unknown_genre.first_seen, unknown_genre.last_seen from unknown_genre, source where unknown_genre.source_id = source.source_id order by unknown_genre.occurrences DESC"
+    )
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// Folds genre rows stored under a non-canonical name into their canonical genre.
+///
+/// Manga keep their genres, and aliases and source genres pointing at the old row are moved over.
+pub async fn consolidate_genres(conn: &mut PoolConnection<MySql>) -> Result<usize> {
+    let all = sqlx::query_as!(Genre, "SELECT genre_id as id, name from genre")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let stale = all
+        .into_iter()
+        .filter(|f| canonical_genre(f.name.as_str()) != f.name)
+        .collect::<Vec<_>>();
+
+    for g in &stale {
+        let name = canonical_genre(g.name.as_str());
+
+        sqlx::query!(
+            "INSERT into genre(genre_id, name) VALUES(?, ?) ON DUPLICATE KEY update genre_id = genre_id",
+            Uuid::new_v4().to_string(),
+            name
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "INSERT IGNORE into manga_genre(manga_id, genre_id) SELECT manga_genre.manga_id, genre.genre_id from manga_genre, genre where manga_genre.genre_id = ? and genre.name = ?",
+            g.id,
+            name
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE genre_alias SET genre_id = (SELECT genre_id from genre where name = ?) where genre_id = ?",
+            name,
+            g.id
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(
+            "UPDATE source_genre SET genre_id = (SELECT genre_id from genre where name = ?) where genre_id = ?",
+            name,
+            g.id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!("DELETE from manga_genre where genre_id = ?", g.id)
+            .execute(&mut *conn)
+            .await?;
+
+        sqlx::query!("DELETE from genre where genre_id = ?", g.id)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(stale.len())
+}
@@ -0,0 +1,201 @@
+use mangaverse_entity::models::chapter::ChapterTable;
+use sqlx::{pool::PoolConnection, types::chrono::Utc, MySql};
+use uuid::Uuid;
+
+use crate::{MSError, Result};
+
+use super::chapter::ChapterOp;
+use super::diff::MangaDiff;
+
+/// Limits on how destructive a single `update_manga` run is allowed to be.
+#[derive(Debug, Clone)]
+pub struct UpdateGuards {
+    /// Largest fraction of stored chapters a single run may delete. Going over it quarantines the
+    /// scrape, or with `quarantine` off writes everything but the deletes.
+    pub max_delete_fraction: f64,
+    /// Keep stored pages when the scrape comes back with none for that chapter
+    pub keep_nonempty_pages: bool,
+    /// Record suspicious scrapes for review instead of writing them
+    pub quarantine: bool,
+}
+
+impl Default for UpdateGuards {
+    fn default() -> Self {
+        Self {
+            max_delete_fraction: 0.5,
+            keep_nonempty_pages: true,
+            quarantine: true,
+        }
+    }
+}
+
+impl UpdateGuards {
+    /// Guards that let everything through, for re-running a scrape that was reviewed and accepted
+    pub fn permissive() -> Self {
+        Self {
+            max_delete_fraction: 1.0,
+            keep_nonempty_pages: false,
+            quarantine: false,
+        }
+    }
+
+    /// Returns why the planned chapter changes look like a broken scrape, if they do. This does not
+    /// depend on `quarantine`, which only decides what happens to such a scrape.
+    pub fn check(&self, stored: &[ChapterTable], ops: &[ChapterOp]) -> Option<String> {
+        if stored.is_empty() {
+            return None;
+        }
+
+        let deleted = ops
+            .iter()
+            .filter(|f| matches!(f, ChapterOp::Delete { .. }))
+            .count();
+
+        let fraction = deleted as f64 / stored.len() as f64;
+
+        if fraction > self.max_delete_fraction {
+            Some(format!(
+                "Scrape would delete {} of {} chapters",
+                deleted,
+                stored.len()
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn allows_page_replacement(&self, ori: &ChapterTable, lat: &ChapterTable) -> bool {
+        !(self.keep_nonempty_pages && lat.pages.is_empty() && !ori.pages.is_empty())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QuarantineEntry {
+    pub quarantine_id: String,
+    pub manga_id: String,
+    pub reason: String,
+    /// The rejected scrape's planned changes as a JSON `MangaDiff`
+    pub scrape: Option<String>,
+    pub created_at: i64,
+}
+
+/// Holds back a scrape, keeping the changes it would have made for the reviewer
+pub async fn quarantine_manga(
+    manga_id: &str,
+    reason: &str,
+    diff: &MangaDiff,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    let scrape = serde_json::to_string(diff).map_err(|e| MSError {
+        message: e.to_string(),
+        err_type: crate::MSErrorType::OtherError,
+    })?;
+
+    sqlx::query!(
+        "INSERT INTO manga_quarantine(quarantine_id, manga_id, reason, scrape, created_at) VALUES(?, ?, ?, ?, ?)",
+        Uuid::new_v4().to_string(),
+        manga_id,
+        reason,
+        scrape,
+        Utc::now().timestamp_millis()
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn is_quarantined(manga_id: &str, conn: &mut PoolConnection<MySql>) -> Result<bool> {
+    Ok(sqlx::query!(
+        "SELECT quarantine_id from manga_quarantine where manga_id = ? and resolved_at is null limit 1",
+        manga_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .is_some())
+}
+
+pub async fn get_quarantined(conn: &mut PoolConnection<MySql>) -> Result<Vec<QuarantineEntry>> {
+    Ok(sqlx::query_as!(
+        QuarantineEntry,
+        "SELECT quarantine_id, manga_id, reason, scrape, created_at from manga_quarantine where resolved_at is null order by created_at ASC"
+    )
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// Closes every open quarantine for the manga so the next `update_manga` run goes ahead.
+pub async fn release_quarantine(manga_id: &str, conn: &mut PoolConnection<MySql>) -> Result<()> {
+    sqlx::query!(
+        "UPDATE manga_quarantine SET resolved_at = ? where manga_id = ? and resolved_at is null",
+        Utc::now().timestamp_millis(),
+        manga_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use mangaverse_entity::models::page::PageTable;
+
+    use super::*;
+
+    fn chapters(n: usize) -> Vec<ChapterTable> {
+        (0..n)
+            .map(|i| ChapterTable {
+                sequence_number: i as i32,
+                pages: vec![PageTable::default()],
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn deleting(n: usize) -> Vec<ChapterOp> {
+        (0..n).map(|ori| ChapterOp::Delete { ori }).collect()
+    }
+
+    #[test]
+    fn allows_deleting_up_to_the_fraction() {
+        let g = UpdateGuards::default();
+        let stored = chapters(10);
+
+        assert_eq!(g.check(&stored, &deleting(4)), None);
+        //exactly at the limit still goes through
+        assert_eq!(g.check(&stored, &deleting(5)), None);
+        assert_eq!(
+            g.check(&stored, &deleting(6)),
+            Some("Scrape would delete 6 of 10 chapters".to_string())
+        );
+    }
+
+    #[test]
+    fn ignores_other_ops_and_empty_manga() {
+        let g = UpdateGuards::default();
+        let stored = chapters(2);
+        let ops = vec![
+            ChapterOp::Delete { ori: 0 },
+            ChapterOp::Update { ori: 1, lat: 0 },
+            ChapterOp::Insert { lat: 1 },
+        ];
+
+        assert_eq!(g.check(&stored, &ops), None);
+        assert_eq!(g.check(&[], &deleting(3)), None);
+        assert_eq!(
+            UpdateGuards::permissive().check(&stored, &deleting(2)),
+            None
+        );
+    }
+
+    #[test]
+    fn keeps_pages_a_scrape_emptied() {
+        let ori = &chapters(1)[0];
+        let emptied = ChapterTable::default();
+        let replaced = &chapters(1)[0];
+
+        assert!(!UpdateGuards::default().allows_page_replacement(ori, &emptied));
+        assert!(UpdateGuards::default().allows_page_replacement(ori, replaced));
+        assert!(UpdateGuards::default().allows_page_replacement(&emptied, &emptied));
+        assert!(UpdateGuards::permissive().allows_page_replacement(ori, &emptied));
+    }
+}
@@ -0,0 +1,627 @@
+use std::collections::HashMap;
+
+use mangaverse_entity::models::manga::MangaTable;
+use sqlx::{
+    mysql::MySqlConnection, pool::PoolConnection, types::chrono::Utc, Acquire, MySql, QueryBuilder,
+    Row,
+};
+use uuid::Uuid;
+
+use crate::linker::normalize::normalize_title;
+use crate::linker::{decide_link, rank_groups, GroupEvidence, LinkConfig, LinkDecision, LinkScore};
+use crate::{MSError, Result};
+
+use super::batch::{fetch_grouped, push_in};
+use super::event::{log_event, log_events, EventKind};
+use super::title::{add_titles, get_claimed_titles};
+
+/// Candidate groups are looked up by their longest words, shorter ones match too much
+const MIN_TOKEN_LEN: usize = 4;
+
+const MAX_CANDIDATES: i64 = 50;
+
+/// Fills in `normalized_title` for titles stored before it existed. Run it once after migrating,
+/// candidate lookups don't see titles without one.
+pub async fn backfill_normalized_titles(conn: &mut PoolConnection<MySql>) -> Result<()> {
+    let rows = sqlx::query!("SELECT title_id, title from title where normalized_title is null")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    for r in rows {
+        sqlx::query!(
+            "UPDATE title SET normalized_title = ? where title_id = ?",
+            normalize_title(r.title.as_str()),
+            r.title_id
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Loads the evidence of several groups at once, in input order
+async fn get_group_evidence(
+    ids: Vec<String>,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<GroupEvidence>> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut q = QueryBuilder::new("SELECT linked_id, title from title where linked_id IN ");
+    push_in(&mut q, ids.iter().map(String::as_str));
+    let mut titles = fetch_grouped(q, conn).await?;
+
+    let mut q = QueryBuilder::new("SELECT DISTINCT manga.linked_id, author.name from author, manga_author, manga where manga_author.author_id = author.author_id and manga_author.manga_id = manga.manga_id and manga.linked_id IN ");
+    push_in(&mut q, ids.iter().map(String::as_str));
+    q.push(" UNION SELECT DISTINCT manga.linked_id, author.name from author, manga_artist, manga where manga_artist.author_id = author.author_id and manga_artist.manga_id = manga.manga_id and manga.linked_id IN ");
+    push_in(&mut q, ids.iter().map(String::as_str));
+    let mut authors = fetch_grouped(q, conn).await?;
+
+    let mut q = QueryBuilder::new("SELECT manga.linked_id, count(*) from chapter, manga where chapter.manga_id = manga.manga_id and manga.is_main = 1 and chapter.removed_at is null and manga.linked_id IN ");
+    push_in(&mut q, ids.iter().map(String::as_str));
+    q.push(" group by manga.linked_id");
+
+    let mut counts = HashMap::new();
+    for r in q.build().fetch_all(&mut *conn).await? {
+        counts.insert(r.try_get::<String, usize>(0)?, r.try_get::<i64, usize>(1)?);
+    }
+
+    Ok(ids
+        .into_iter()
+        .map(|f| GroupEvidence {
+            titles: titles.remove(&f).unwrap_or_default(),
+            authors: authors.remove(&f).unwrap_or_default(),
+            chapter_count: counts.get(&f).copied().unwrap_or_default() as usize,
+            linked_id: f,
+        })
+        .collect())
+}
+
+fn fetch_ids(rows: Vec<sqlx::mysql::MySqlRow>) -> std::result::Result<Vec<String>, sqlx::Error> {
+    rows.into_iter()
+        .map(|f| f.try_get::<String, usize>(0))
+        .collect()
+}
+
+/// Gathers groups sharing a normalized title or a long title word with the manga.
+///
+/// Every group with an exactly matching title is taken. Groups only sharing a word are found
+/// through the fulltext index on `normalized_title`, ranked by its relevance and then by how
+/// short the matching title is, and only the best `MAX_CANDIDATES` of them are taken.
+pub async fn get_candidate_groups(
+    mng: &MangaTable<'_>,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<GroupEvidence>> {
+    let normalized: Vec<String> = mng.titles.iter().map(|f| normalize_title(f)).collect();
+
+    if normalized.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut q = QueryBuilder::new("SELECT DISTINCT linked_id from title where linked_id != ");
+    q.push_bind(mng.linked_id.as_str());
+    q.push(" and normalized_title IN ");
+    push_in(&mut q, normalized.iter().map(String::as_str));
+
+    let mut ids = fetch_ids(q.build().fetch_all(&mut *conn).await?)?;
+
+    //normalized titles only hold letters, digits and spaces, so the words can go to the
+    //fulltext search as they are
+    let tokens: Vec<&str> = normalized
+        .iter()
+        .filter_map(|f| f.split(' ').max_by_key(|t| t.chars().count()))
+        .filter(|f| f.chars().count() >= MIN_TOKEN_LEN)
+        .collect();
+
+    if !tokens.is_empty() {
+        let words = tokens.join(" ");
+
+        let mut q = QueryBuilder::new("SELECT linked_id from title where linked_id != ");
+        q.push_bind(mng.linked_id.as_str());
+
+        if !ids.is_empty() {
+            q.push(" and linked_id NOT IN ");
+            push_in(&mut q, ids.iter().map(String::as_str));
+        }
+
+        q.push(" and MATCH(normalized_title) AGAINST (");
+        q.push_bind(words.as_str());
+        q.push(
+            " IN BOOLEAN MODE) group by linked_id order by max(MATCH(normalized_title) AGAINST (",
+        );
+        q.push_bind(words.as_str());
+        q.push(
+            " IN BOOLEAN MODE)) DESC, min(char_length(normalized_title)) ASC, linked_id ASC limit ",
+        );
+        q.push_bind(MAX_CANDIDATES);
+
+        ids.extend(fetch_ids(q.build().fetch_all(&mut *conn).await?)?);
+    }
+
+    get_group_evidence(ids, conn).await
+}
+
+/// Scores every candidate group for the manga, best first
+pub async fn score_candidates(
+    mng: &MangaTable<'_>,
+    cfg: &LinkConfig,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<LinkScore>> {
+    let groups = get_candidate_groups(mng, conn).await?;
+    Ok(rank_groups(mng, &groups, cfg))
+}
+
+/// Scores the candidate groups and decides whether the manga can be linked without a human
+pub async fn decide_candidates(
+    mng: &MangaTable<'_>,
+    cfg: &LinkConfig,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<LinkDecision> {
+    Ok(decide_link(score_candidates(mng, cfg, conn).await?, cfg))
+}
+
+/// Takes the manga's titles from its old group to its new one. Titles of the old group that no
+/// remaining member claims go away with it.
+pub(crate) async fn move_titles(
+    manga_id: &str,
+    from: &str,
+    to: &str,
+    conn: &mut MySqlConnection,
+) -> Result<()> {
+    if from == to {
+        return Ok(());
+    }
+
+    let claimed = get_claimed_titles(manga_id, conn).await?;
+
+    add_titles(manga_id, to, claimed.as_slice(), conn).await?;
+
+    let remaining = sqlx::query!(
+        "SELECT count(*) as data from manga where linked_id = ?",
+        from
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .data;
+
+    if remaining == 0 {
+        sqlx::query!("DELETE FROM title where linked_id = ?", from)
+            .execute(&mut *conn)
+            .await?;
+        return Ok(());
+    }
+
+    for t in &claimed {
+        sqlx::query!(
+            "DELETE FROM title where title = ? and linked_id = ? and NOT EXISTS (
+                SELECT manga_title.title FROM manga_title, manga
+                WHERE manga_title.manga_id = manga.manga_id AND manga.linked_id = ? AND manga_title.title = ?
+            )",
+            t.as_str(),
+            from,
+            from,
+            t.as_str()
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Makes the manga from the highest ranked enabled source the only main one of its group. Ties
+/// keep the current main, and disabled sources only win when nothing else is left.
+///
+/// Returns the id of the main manga, or `None` for a group with no manga left.
+pub async fn elect_main(linked_id: &str, conn: &mut MySqlConnection) -> Result<Option<String>> {
+    let winner = sqlx::query!(
+        "SELECT manga.manga_id from manga, source where manga.source_id = source.source_id and manga.linked_id = ? order by source.is_enabled DESC, source.priority ASC, manga.is_main DESC, manga.manga_id ASC limit 1",
+        linked_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .map(|f| f.manga_id);
+
+    if let Some(w) = &winner {
+        let mut txt = conn.begin().await?;
+
+        sqlx::query!(
+            "UPDATE manga set is_main = 0 where linked_id = ? and manga_id != ?",
+            linked_id,
+            w
+        )
+        .execute(&mut txt)
+        .await?;
+        sqlx::query!("UPDATE manga set is_main = 1 where manga_id = ?", w)
+            .execute(&mut txt)
+            .await?;
+
+        txt.commit().await?;
+    }
+
+    Ok(winner)
+}
+
+/// Moves a manga into a group, taking its titles along and re-electing the main manga of both the
+/// group it left and the one it joined.
+pub async fn link_manga(
+    manga_id: &str,
+    linked_id: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    let mut txt = conn.begin().await?;
+    regroup(manga_id, linked_id, EventKind::MangaLinked, &mut txt).await?;
+    txt.commit().await?;
+    Ok(())
+}
+
+/// Does the move for `link_manga` and `unlink_manga`, logging it as `kind`. Callers run it in a
+/// transaction so a group is never left without its titles or its main manga.
+async fn regroup(
+    manga_id: &str,
+    linked_id: &str,
+    kind: EventKind,
+    conn: &mut MySqlConnection,
+) -> Result<()> {
+    let current = sqlx::query!("SELECT linked_id from manga where manga_id = ?", manga_id)
+        .fetch_one(&mut *conn)
+        .await?
+        .linked_id;
+
+    if current == linked_id {
+        return Ok(());
+    }
+
+    sqlx::query!(
+        "UPDATE manga set linked_id = ?, is_main = 0 where manga_id = ?",
+        linked_id,
+        manga_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    move_titles(manga_id, current.as_str(), linked_id, conn).await?;
+
+    elect_main(current.as_str(), conn).await?;
+    elect_main(linked_id, conn).await?;
+
+    log_event(manga_id, kind, current.as_str(), linked_id, conn).await
+}
+
+/// Splits a manga out into a group of its own. Returns the new `linked_id`.
+pub async fn unlink_manga(manga_id: &str, conn: &mut PoolConnection<MySql>) -> Result<String> {
+    let linked_id = Uuid::new_v4().to_string();
+
+    let mut txt = conn.begin().await?;
+    regroup(
+        manga_id,
+        linked_id.as_str(),
+        EventKind::MangaUnlinked,
+        &mut txt,
+    )
+    .await?;
+    txt.commit().await?;
+
+    Ok(linked_id)
+}
+
+/// Moves every manga and title of `from` into `into` and re-elects the main manga.
+pub async fn merge_groups(into: &str, from: &str, conn: &mut PoolConnection<MySql>) -> Result<()> {
+    if into == from {
+        return Ok(());
+    }
+
+    let mut txt = conn.begin().await?;
+
+    let moved: Vec<String> = sqlx::query!("SELECT manga_id from manga where linked_id = ?", from)
+        .fetch_all(&mut txt)
+        .await?
+        .into_iter()
+        .map(|f| f.manga_id)
+        .collect();
+
+    sqlx::query!(
+        "UPDATE manga set linked_id = ? where linked_id = ?",
+        into,
+        from
+    )
+    .execute(&mut txt)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE title set linked_id = ? where linked_id = ? and title NOT IN (SELECT t.title FROM (SELECT title FROM title WHERE linked_id = ?) AS t)",
+        into,
+        from,
+        into
+    )
+    .execute(&mut txt)
+    .await?;
+
+    //whatever is left is already in the other group
+    sqlx::query!("DELETE FROM title where linked_id = ?", from)
+        .execute(&mut txt)
+        .await?;
+
+    sqlx::query!(
+        "UPDATE link_review set linked_id = ? where linked_id = ?",
+        into,
+        from
+    )
+    .execute(&mut txt)
+    .await?;
+
+    log_events(
+        moved
+            .iter()
+            .map(|f| {
+                (
+                    f.as_str(),
+                    EventKind::GroupsMerged,
+                    from.to_string(),
+                    into.to_string(),
+                )
+            })
+            .collect::<Vec<_>>()
+            .as_slice(),
+        &mut txt,
+    )
+    .await?;
+
+    elect_main(into, &mut txt).await?;
+
+    txt.commit().await?;
+
+    Ok(())
+}
+
+/// Puts a freshly inserted manga into the group it was matched to.
+///
+/// A group already holding another manga from the same source is taken to be a different series
+/// from the same site, so the manga is left in a group of its own. Returns the `linked_id` the
+/// manga ends up with.
+pub async fn join_group(
+    manga_id: &str,
+    source_id: &str,
+    linked_id: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<String> {
+    let same_source = sqlx::query!(
+        "SELECT count(*) as data from manga where linked_id = ? and source_id = ? and manga_id != ?",
+        linked_id,
+        source_id,
+        manga_id
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .data;
+
+    if same_source > 0 {
+        let current = sqlx::query!("SELECT linked_id from manga where manga_id = ?", manga_id)
+            .fetch_one(&mut *conn)
+            .await?
+            .linked_id;
+
+        elect_main(current.as_str(), conn).await?;
+
+        return Ok(current);
+    }
+
+    link_manga(manga_id, linked_id, conn).await?;
+
+    Ok(linked_id.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewStatus {
+    Pending,
+    Accepted,
+    Rejected,
+    /// Another candidate for the same manga was accepted
+    Superseded,
+}
+
+impl ReviewStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReviewStatus::Pending => "pending",
+            ReviewStatus::Accepted => "accepted",
+            ReviewStatus::Rejected => "rejected",
+            ReviewStatus::Superseded => "superseded",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewReason {
+    /// The group scored below the link threshold
+    LowScore,
+    /// The group scored about as well as another one
+    Conflict,
+}
+
+impl ReviewReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReviewReason::LowScore => "low_score",
+            ReviewReason::Conflict => "conflict",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkReview {
+    pub review_id: String,
+    pub manga_id: String,
+    pub linked_id: String,
+    pub score: f64,
+    pub title_score: f64,
+    pub author_score: Option<f64>,
+    pub chapter_score: Option<f64>,
+    pub matching_titles: Vec<String>,
+    pub matching_authors: Vec<String>,
+    pub reason: String,
+    pub created_at: i64,
+}
+
+struct LinkReviewRow {
+    review_id: String,
+    manga_id: String,
+    linked_id: String,
+    score: f64,
+    title_score: f64,
+    author_score: Option<f64>,
+    chapter_score: Option<f64>,
+    matching_titles: String,
+    matching_authors: String,
+    reason: String,
+    created_at: i64,
+}
+
+fn split_lines(s: String) -> Vec<String> {
+    s.lines()
+        .filter(|f| !f.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+impl From<LinkReviewRow> for LinkReview {
+    fn from(a: LinkReviewRow) -> Self {
+        Self {
+            review_id: a.review_id,
+            manga_id: a.manga_id,
+            linked_id: a.linked_id,
+            score: a.score,
+            title_score: a.title_score,
+            author_score: a.author_score,
+            chapter_score: a.chapter_score,
+            matching_titles: split_lines(a.matching_titles),
+            matching_authors: split_lines(a.matching_authors),
+            reason: a.reason,
+            created_at: a.created_at,
+        }
+    }
+}
+
+pub async fn queue_link_reviews(
+    manga_id: &str,
+    candidates: &[LinkScore],
+    reason: ReviewReason,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    for l in candidates {
+        sqlx::query!(
+            "INSERT INTO link_review(review_id, manga_id, linked_id, score, title_score, author_score, chapter_score, matching_titles, matching_authors, reason, status, created_at) VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            Uuid::new_v4().to_string(),
+            manga_id,
+            l.linked_id,
+            l.score,
+            l.title_score,
+            l.author_score,
+            l.chapter_score,
+            l.matching_titles.join("\n"),
+            l.matching_authors.join("\n"),
+            reason.as_str(),
+            ReviewStatus::Pending.as_str(),
+            Utc::now().timestamp_millis()
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn get_pending_link_reviews(conn: &mut PoolConnection<MySql>) -> Result<Vec<LinkReview>> {
+    Ok(sqlx::query_as!(
+        LinkReviewRow,
+        "SELECT review_id, manga_id, linked_id, score, title_score, author_score, chapter_score, matching_titles, matching_authors, reason, created_at from link_review where status = ? order by score DESC",
+        ReviewStatus::Pending.as_str()
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(Into::into)
+    .collect())
+}
+
+/// Pending reviews raised because a manga matched several groups about equally well
+pub async fn get_link_conflicts(conn: &mut PoolConnection<MySql>) -> Result<Vec<LinkReview>> {
+    Ok(get_pending_link_reviews(conn)
+        .await?
+        .into_iter()
+        .filter(|f| f.reason == ReviewReason::Conflict.as_str())
+        .collect())
+}
+
+async fn get_pending_link_review(
+    review_id: &str,
+    conn: &mut MySqlConnection,
+) -> Result<LinkReview> {
+    sqlx::query_as!(
+        LinkReviewRow,
+        "SELECT review_id, manga_id, linked_id, score, title_score, author_score, chapter_score, matching_titles, matching_authors, reason, created_at from link_review where review_id = ? and status = ?",
+        review_id,
+        ReviewStatus::Pending.as_str()
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .map(Into::into)
+    .ok_or(MSError {
+        message: format!("No pending link review {}", review_id),
+        err_type: crate::MSErrorType::OtherError,
+    })
+}
+
+async fn set_review_status(
+    review_id: &str,
+    status: ReviewStatus,
+    conn: &mut MySqlConnection,
+) -> Result<()> {
+    sqlx::query!(
+        "UPDATE link_review SET status = ?, resolved_at = ? where review_id = ?",
+        status.as_str(),
+        Utc::now().timestamp_millis(),
+        review_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Links the manga into the reviewed group and re-elects its main manga.
+pub async fn accept_link_review(review_id: &str, conn: &mut PoolConnection<MySql>) -> Result<()> {
+    let mut txt = conn.begin().await?;
+
+    let r = get_pending_link_review(review_id, &mut txt).await?;
+
+    regroup(
+        r.manga_id.as_str(),
+        r.linked_id.as_str(),
+        EventKind::MangaLinked,
+        &mut txt,
+    )
+    .await?;
+
+    set_review_status(review_id, ReviewStatus::Accepted, &mut txt).await?;
+
+    sqlx::query!(
+        "UPDATE link_review SET status = ?, resolved_at = ? where manga_id = ? and status = ?",
+        ReviewStatus::Superseded.as_str(),
+        Utc::now().timestamp_millis(),
+        r.manga_id,
+        ReviewStatus::Pending.as_str()
+    )
+    .execute(&mut txt)
+    .await?;
+
+    txt.commit().await?;
+
+    Ok(())
+}
+
+pub async fn reject_link_review(review_id: &str, conn: &mut PoolConnection<MySql>) -> Result<()> {
+    get_pending_link_review(review_id, conn).await?;
+    set_review_status(review_id, ReviewStatus::Rejected, conn).await
+}
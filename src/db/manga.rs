@@ -1,20 +1,41 @@
-use std::cmp::Ordering;
-
-use crate::{Context, Result};
+use std::sync::Arc;
+
+use crate::linker::{LinkConfig, LinkDecision};
+use crate::mirror::cover::CoverCache;
+use crate::notify::Notifications;
+use crate::parse::date::{same_date, RelativeDates};
+use crate::parse::status::{normalize_status, Status};
+use crate::parse::text::truncate;
+use crate::{Context, MSError, Result};
 use inflector::Inflector;
 use itertools::Itertools;
 use lazy_static::lazy_static;
 use mangaverse_entity::models::chapter::ChapterTable;
 use mangaverse_entity::models::manga::MangaTable;
-use mangaverse_entity::models::page::PageTable;
 use mangaverse_entity::models::source::SourceTable;
 use sqlx::mysql::MySqlRow;
 use sqlx::pool::PoolConnection;
-use sqlx::types::chrono::{NaiveDateTime, Utc};
-use sqlx::{FromRow, MySql, QueryBuilder, Row, Acquire};
+use sqlx::types::chrono::Utc;
+use sqlx::{FromRow, MySql, QueryBuilder, Row};
 use uuid::Uuid;
 
-use super::chapter::{add_extra_chaps, delete_extra_chaps, update_chapter};
+use super::author::{
+    add_manga_authors, insert_authors, normalize_people, remove_manga_authors, AuthorRole,
+};
+use super::batch::load_chapters;
+use super::chapter::{
+    add_extra_chaps, claim_chapter, delete_extra_chaps, diff_chapters, get_chapter_urls,
+    move_chapter, set_chapter_url, update_chapter, ChapterOp,
+};
+use super::cover::QUEUED_GRACE;
+use super::diff::{diff_manga, MangaDiff};
+use super::event::{chapter_summary, log_event, EventKind};
+use super::guard::{is_quarantined, quarantine_manga, UpdateGuards};
+use super::link::{decide_candidates, join_group, queue_link_reviews, ReviewReason};
+use super::title::{add_titles, clean_titles, get_claimed_titles, remove_titles};
+
+/// Characters kept of the description in `manga_listing`, ellipsis included
+const DESCRIPTION_SMALL_LEN: usize = 255;
 
 lazy_static! {
     static ref JUNK_SOURCE: SourceTable = SourceTable {
@@ -27,6 +48,8 @@ lazy_static! {
 pub struct MangaTableWrapper<'a> {
     pub contents: MangaTable<'a>,
     pub source_id: String,
+    /// Normalized form of `contents.status`
+    pub publication_status: Status,
 }
 
 impl FromRow<'_, MySqlRow> for MangaTableWrapper<'_> {
@@ -54,32 +77,105 @@ impl FromRow<'_, MySqlRow> for MangaTableWrapper<'_> {
                 titles: Vec::default(),
             },
             source_id: row.try_get("source_id")?,
+            publication_status: Status::from_code(row.try_get("publication_status")?),
         })
     }
 }
 
+/// Brings a stored manga in line with a fresh scrape and reports what changed.
+///
+/// `relative` tells which scraped dates were relative, those keep the stored date while they only
+/// drift within their unit. With `dry_run` set nothing is written and the returned diff shows what
+/// would have been.
+/// Newly stored chapters are announced through `notifications` in the background, so slow
+/// subscribers don't hold the connection. A changed cover is queued on `covers` the same way.
 pub async fn update_manga(
     stored: &MangaTable<'_>,
     mng: &mut MangaTable<'_>,
+    relative: &RelativeDates,
+    guards: &UpdateGuards,
+    dry_run: bool,
+    notifications: Option<&Arc<Notifications>>,
+    covers: Option<&Arc<CoverCache>>,
     conn: &mut PoolConnection<MySql>,
-) -> Result<()> {
+) -> Result<MangaDiff> {
     println!("Checking {}", stored.url);
 
-    let t1 = stored.name == mng.name
-        && stored.cover_url == mng.cover_url
-        && stored.description == mng.description;
+    if is_quarantined(stored.id.as_str(), conn).await? {
+        println!("Skipping {}, a previous scrape is awaiting review", stored.url);
+        return Ok(MangaDiff {
+            manga_id: stored.id.clone(),
+            skipped: true,
+            ..Default::default()
+        });
+    }
+
+    normalize_people(mng);
+    clean_titles(&mut mng.titles);
+
+    //a relative date that merely drifted keeps the stored one
+    if same_date(
+        stored.last_updated,
+        mng.last_updated,
+        relative.last_updated,
+        Utc::now().naive_utc(),
+    ) {
+        mng.last_updated = stored.last_updated;
+    }
+
+    let claimed_titles = get_claimed_titles(stored.id.as_str(), conn).await?;
+
+    let stored_urls = get_chapter_urls(stored.id.as_str(), conn).await?;
 
-    let t = t1 && stored.last_updated == mng.last_updated && stored.status == mng.status;
+    let mut ops = diff_chapters(&stored.chapters, &stored_urls, &mng.chapters, relative);
 
-    let f = stored.genres == mng.genres;
+    let mut diff = diff_manga(stored, mng, &claimed_titles, &ops, guards);
 
-    if !t {
+    if let Some(reason) = guards.check(&stored.chapters, &ops) {
+        if guards.quarantine {
+            println!("Quarantining {}: {}", stored.url, reason);
+            if !dry_run {
+                quarantine_manga(stored.id.as_str(), reason.as_str(), &diff, conn).await?;
+            }
+            diff.quarantined = Some(reason);
+            return Ok(diff);
+        }
+
+        println!("Not deleting chapters of {}: {}", stored.url, reason);
+        ops.retain(|f| !matches!(f, ChapterOp::Delete { .. }));
+        diff.chapters_removed.clear();
+        diff.held_back.push(reason);
+    }
+
+    if dry_run {
+        return Ok(diff);
+    }
+
+    if !diff.fields.is_empty() {
         println!("Updating Metadata for {}", stored.url);
         // update sql
-        sqlx::query!("UPDATE manga SET name = ?, cover_url = ?, last_updated = ?, status = ?, description = ? where manga_id = ?", mng.name, mng.cover_url, mng.last_updated, mng.status, mng.description, stored.id).execute(&mut *conn).await?;
+        let publication_status = normalize_status(mng.source.name.as_str(), mng.status.as_str());
+        sqlx::query!("UPDATE manga SET name = ?, cover_url = ?, last_updated = ?, status = ?, publication_status = ?, description = ? where manga_id = ?", mng.name, mng.cover_url, mng.last_updated, mng.status, publication_status.as_str(), mng.description, stored.id).execute(&mut *conn).await?;
+
+        log_event(
+            stored.id.as_str(),
+            EventKind::MetadataUpdated,
+            diff.fields
+                .iter()
+                .map(|f| format!("{}: {}", f.field, f.before))
+                .join("\n")
+                .as_str(),
+            diff.fields
+                .iter()
+                .map(|f| format!("{}: {}", f.field, f.after))
+                .join("\n")
+                .as_str(),
+            conn,
+        )
+        .await?;
     }
 
-    if !f || !t1 {
+    if diff.listing_changed() {
         println!("Updating Manga Listing for {}", stored.url);
 
         let genres_all = itertools::Itertools::intersperse(
@@ -87,75 +183,206 @@ pub async fn update_manga(
             ", ".to_string(),
         )
         .collect::<String>();
-        let description_small = &mng.description[..255.min(mng.description.len())];
+        let description_small = truncate(mng.description.as_str(), DESCRIPTION_SMALL_LEN);
+        let publication_status = normalize_status(mng.source.name.as_str(), mng.status.as_str());
 
+        //a new cover is recorded as due for a retry once its queued refresh had time to finish
         sqlx::query!(
-            "UPDATE manga_listing SET cover_url = ? , name = ?, genres = ?, description_small = ? where manga_id = ?",
+            "UPDATE manga_listing SET cover_retry_at = if(cover_url <=> ?, cover_retry_at, ?), cover_url = ? , name = ?, genres = ?, description_small = ?, publication_status = ? where manga_id = ?",
+            mng.cover_url,
+            Utc::now().timestamp_millis() + QUEUED_GRACE,
             mng.cover_url,
             mng.name,
             genres_all,
             description_small,
+            publication_status.as_str(),
             stored.id
         )
         .execute(&mut *conn)
         .await?;
     }
 
+    if let Some(cc) = covers.filter(|_| diff.has_field("cover_url")) {
+        cc.queue(stored.id.as_str(), mng.cover_url.as_str(), mng.url.as_str());
+    }
+
     //handle collection updates probably by a generic function
 
-    if !f {
+    if diff.genres_changed() {
         sqlx::query!("DELETE from manga_genre where manga_id = ?", stored.id)
             .execute(&mut *conn)
             .await?;
 
-        let mut q = QueryBuilder::new("INSERT into manga_genre(manga_id, genre_id) ");
+        if !mng.genres.is_empty() {
+            let mut q = QueryBuilder::new("INSERT into manga_genre(manga_id, genre_id) ");
 
-        q.push_values(mng.genres.as_slice(), |mut b, genre| {
-            b.push_bind(stored.id.as_str());
-            b.push_bind(genre.id.as_str());
-        });
+            q.push_values(mng.genres.as_slice(), |mut b, genre| {
+                b.push_bind(stored.id.as_str());
+                b.push_bind(genre.id.as_str());
+            });
 
-        q.build().execute(&mut *conn).await?;
+            q.build().execute(&mut *conn).await?;
+        }
+
+        log_event(
+            stored.id.as_str(),
+            EventKind::GenresUpdated,
+            stored.genres.iter().map(|f| f.name.as_str()).join(", ").as_str(),
+            mng.genres.iter().map(|f| f.name.as_str()).join(", ").as_str(),
+            conn,
+        )
+        .await?;
 
         println!("Inserted updated genres into manga");
     }
 
-    let fut = stored.chapters.iter().zip(mng.chapters.iter());
-
-    for (a, b) in fut {
-        let f = update_chapter(a, b, conn).await;
-        if f.is_err() {
-            println!("{:#?}", f.expect_err("If Check failed"));
+    for (role, kind, added, removed, before, after) in [
+        (
+            AuthorRole::Author,
+            EventKind::AuthorsUpdated,
+            &diff.authors_added,
+            &diff.authors_removed,
+            &stored.authors,
+            &mng.authors,
+        ),
+        (
+            AuthorRole::Artist,
+            EventKind::ArtistsUpdated,
+            &diff.artists_added,
+            &diff.artists_removed,
+            &stored.artists,
+            &mng.artists,
+        ),
+    ] {
+        if added.is_empty() && removed.is_empty() {
+            continue;
         }
+
+        insert_authors(added.iter(), conn).await?;
+        add_manga_authors(stored.id.as_str(), added, role, conn).await?;
+        remove_manga_authors(stored.id.as_str(), removed, role, conn).await?;
+
+        log_event(
+            stored.id.as_str(),
+            kind,
+            before.join(", ").as_str(),
+            after.join(", ").as_str(),
+            conn,
+        )
+        .await?;
     }
 
-    match stored.chapters.len().cmp(&mng.chapters.len()) {
-        Ordering::Less => {
-            //add extra
-            println!("Yay! New Chapters added for {}", stored.url);
-            for r in &mut mng.chapters[stored.chapters.len()..] {
-                r.chapter_id = Uuid::new_v4().to_string();
-                r.manga_id = stored.id.clone();
+    if !diff.titles_added.is_empty() || !diff.titles_removed.is_empty() {
+        add_titles(
+            stored.id.as_str(),
+            stored.linked_id.as_str(),
+            diff.titles_added.as_slice(),
+            conn,
+        )
+        .await?;
+        remove_titles(
+            stored.id.as_str(),
+            stored.linked_id.as_str(),
+            diff.titles_removed.as_slice(),
+            conn,
+        )
+        .await?;
+
+        log_event(
+            stored.id.as_str(),
+            EventKind::TitlesUpdated,
+            claimed_titles.join(", ").as_str(),
+            mng.titles.join(", ").as_str(),
+            conn,
+        )
+        .await?;
+    }
+
+    if !diff.chapters_removed.is_empty() {
+        println!(
+            "Deleting {} chapters for {}... strange",
+            diff.chapters_removed.len(),
+            stored.url
+        );
+        delete_extra_chaps(
+            diff.chapters_removed
+                .iter()
+                .map(|f| f.chapter_id.as_str())
+                .collect::<Vec<_>>()
+                .as_slice(),
+            conn,
+        )
+        .await?;
+    }
+
+    for op in &ops {
+        let (ori, lat) = match *op {
+            ChapterOp::Update { ori, lat }
+            | ChapterOp::Move { ori, lat }
+            | ChapterOp::Keep { ori, lat } => (&stored.chapters[ori], &mng.chapters[lat]),
+            ChapterOp::Insert { .. } | ChapterOp::Delete { .. } => continue,
+        };
+
+        let f = match op {
+            ChapterOp::Update { .. } => {
+                let rel = relative.chapters.contains(&lat.chapter_id);
+                update_chapter(ori, lat, rel, guards, conn).await
+            }
+            ChapterOp::Move { .. } => {
+                match move_chapter(ori.chapter_id.as_str(), lat.sequence_number, conn).await {
+                    Ok(_) => {
+                        log_event(
+                            stored.id.as_str(),
+                            EventKind::ChapterUpdated,
+                            chapter_summary(ori).as_str(),
+                            chapter_summary(lat).as_str(),
+                            conn,
+                        )
+                        .await
+                    }
+                    e => e,
+                }
             }
-            add_extra_chaps(&mng.chapters[stored.chapters.len()..], conn).await?;
+            _ => Ok(()),
+        };
+        if let Err(e) = f {
+            println!("{:#?}", e);
         }
-        Ordering::Greater => {
-            //delete extra
-            println!("Deleting chapters for {}... strange", stored.url);
-            delete_extra_chaps(
-                stored
-                    .chapters
-                    .iter()
-                    .skip(mng.chapters.len())
-                    .map(|f| f.chapter_id.as_str())
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-                conn,
-            )
-            .await?;
+
+        //chapters stored before source urls were tracked get theirs now
+        if !stored_urls.contains_key(&ori.chapter_id) {
+            set_chapter_url(ori.chapter_id.as_str(), lat.chapter_id.as_str(), conn).await?;
         }
-        Ordering::Equal => {
-            println!("No chapter updates for {}", stored.url);
+    }
+
+    let new_urls = ops
+        .iter()
+        .filter_map(|op| match *op {
+            ChapterOp::Insert { lat } => Some(lat),
+            _ => None,
+        })
+        .map(|lat| (lat, claim_chapter(&mut mng.chapters[lat], stored.id.as_str())))
+        .collect::<Vec<_>>();
+
+    if !new_urls.is_empty() {
+        println!("Yay! New Chapters added for {}", stored.url);
+        add_extra_chaps(
+            new_urls
+                .iter()
+                .map(|(lat, u)| (u.as_str(), &mng.chapters[*lat]))
+                .collect::<Vec<_>>()
+                .as_slice(),
+            conn,
+        )
+        .await?;
+
+        //report the ids the new chapters were stored under
+        for (s, (lat, _)) in diff.chapters_added.iter_mut().zip(new_urls.iter()) {
+            s.chapter_id = mng.chapters[*lat].chapter_id.clone();
+        }
+
+        if let Some(n) = notifications {
+            n.announce(stored, &diff);
         }
     }
 
@@ -167,7 +394,7 @@ pub async fn update_manga(
     .execute(&mut *conn)
     .await?;
 
-    Ok(())
+    Ok(diff)
 }
 
 pub async fn get_manga_from_url<'a>(
@@ -200,6 +427,17 @@ pub async fn get_manga_from_id<'a>(
     Ok(r.contents)
 }
 
+/// `Context.sources` is keyed by name, manga rows only know the source id
+pub(crate) fn source_by_id<'a>(c: &'a Context, source_id: &str) -> Result<&'a SourceTable> {
+    c.sources
+        .values()
+        .find(|f| f.id == source_id)
+        .ok_or_else(|| MSError {
+            message: format!("Unknown source {}", source_id),
+            err_type: crate::MSErrorType::OtherError,
+        })
+}
+
 async fn populate_relations<'a>(
     r: &mut MangaTableWrapper<'a>,
     conn: &mut PoolConnection<MySql>,
@@ -245,19 +483,7 @@ async fn populate_relations<'a>(
     .filter_map(|f| c.genres.get(f.data.as_str()))
     .collect();
 
-    r.contents.source = c
-        .sources
-        .get(
-            sqlx::query!(
-                "SELECT source_id as data from source where source_id = ?",
-                r.source_id
-            )
-            .fetch_one(&mut *conn)
-            .await?
-            .data
-            .as_str(),
-        )
-        .unwrap();
+    r.contents.source = source_by_id(c, r.source_id.as_str())?;
 
     r.contents.chapters = get_chapters(r.contents.id.as_str(), conn).await?;
 
@@ -266,6 +492,8 @@ async fn populate_relations<'a>(
 
 pub async fn insert_manga(
     mng: &mut MangaTable<'_>,
+    link_cfg: &LinkConfig,
+    covers: Option<&Arc<CoverCache>>,
     conn: &mut PoolConnection<MySql>,
 ) -> Result<()> {
     //WIP
@@ -276,100 +504,63 @@ pub async fn insert_manga(
     mng.linked_id = Uuid::new_v4().to_string();
     mng.last_watch_time = Some(Utc::now().timestamp_millis());
     mng.public_id = Uuid::new_v4().to_string();
-    mng.artists = mng.artists.iter().map(|f| f.to_lowercase()).collect();
-    mng.authors = mng.authors.iter().map(|f| f.to_uppercase()).collect();
+    normalize_people(mng);
+    clean_titles(&mut mng.titles);
 
     //insert metadata
 
-    sqlx::query!("INSERT INTO manga(manga_id, linked_id, is_listed, name, cover_url, url, last_updated, status, is_main, description, source_id, last_watch_time, public_id, is_old) VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)", mng.id, mng.linked_id, true, mng.name, mng.cover_url, mng.url, mng.last_updated, mng.status, false, mng.description, mng.source.id, mng.last_watch_time, mng.public_id, false).execute(&mut *conn).await?;
+    let publication_status = normalize_status(mng.source.name.as_str(), mng.status.as_str());
 
-    //look for matches using the titles table and set priority and linked_id
+    sqlx::query!("INSERT INTO manga(manga_id, linked_id, is_listed, name, cover_url, url, last_updated, status, publication_status, is_main, description, source_id, last_watch_time, public_id, is_old) VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)", mng.id, mng.linked_id, true, mng.name, mng.cover_url, mng.url, mng.last_updated, mng.status, publication_status.as_str(), false, mng.description, mng.source.id, mng.last_watch_time, mng.public_id, false).execute(&mut *conn).await?;
 
-    let mut q = QueryBuilder::new("SELECT source.priority, manga.linked_id FROM source, manga where manga.source_id = source.source_id AND manga.is_main = 1 AND manga.linked_id = (select linked_id from title where title IN (");
-
-    let mut sep = q.separated(',');
-
-    for t in &mng.titles {
-        sep.push_bind(t);
-    }
-
-    q.push(") limit 1)");
+    //look for matches using the titles table and set priority and linked_id
 
-    let pri = q.build().fetch_optional(&mut *conn).await?;
+    let decision = decide_candidates(mng, link_cfg, conn).await?;
 
     println!("After priorty fetch");
 
-    if let Some(p) = pri {
-        let act_pri = p.try_get::<i32, usize>(0)?;
-        let act_link = p.try_get::<String, usize>(1)?;
-
-        match mng.source.priority.cmp(&act_pri) {
-            Ordering::Equal => {
-                //break link... it's actually different
-                sqlx::query!("UPDATE manga set is_main = 1 where manga_id = ?", mng.id)
-                    .execute(&mut *conn)
-                    .await?;
-            }
-            Ordering::Greater => {
-                mng.linked_id = act_link;
-                sqlx::query!(
-                    "UPDATE manga set linked_id = ? where manga_id = ?",
-                    mng.linked_id,
-                    mng.id
-                )
-                .execute(&mut *conn)
-                .await?;
-            }
-            Ordering::Less => {
-                mng.linked_id = act_link;
-                let mut txt = conn.begin().await?;
-                
-                sqlx::query!(
-                    "UPDATE manga set linked_id = ? where manga_id = ?",
-                    mng.linked_id,
-                    mng.id
-                )
-                .execute(&mut txt)
-                .await?;
-                sqlx::query!(
-                    "UPDATE manga set is_main = 0 where linked_id = ?",
-                    mng.linked_id
-                )
-                .execute(&mut txt)
-                .await?;
-                sqlx::query!("UPDATE manga set is_main = 1 where manga_id = ?", mng.id)
-                    .execute(&mut txt)
-                    .await?;
-
-                txt.commit().await?;
-            }
-        }
+    if let LinkDecision::Link(l) = &decision {
+        println!(
+            "Linking {} to group {} (score {:.2})",
+            mng.url, l.linked_id, l.score
+        );
+        mng.linked_id = join_group(
+            mng.id.as_str(),
+            mng.source.id.as_str(),
+            l.linked_id.as_str(),
+            conn,
+        )
+        .await?;
     } else {
         sqlx::query!("UPDATE manga set is_main = 1 where manga_id = ?", mng.id)
             .execute(&mut *conn)
             .await?;
     }
 
-    println!("After is main update");
+    match &decision {
+        LinkDecision::Conflict(c) => {
+            println!(
+                "{} matches {} groups equally well, queued for review",
+                mng.url,
+                c.len()
+            );
+            queue_link_reviews(mng.id.as_str(), c, ReviewReason::Conflict, conn).await?;
+        }
+        LinkDecision::Review(c) => {
+            queue_link_reviews(mng.id.as_str(), c, ReviewReason::LowScore, conn).await?;
+        }
+        LinkDecision::Link(_) | LinkDecision::Unmatched => {}
+    }
 
-    //what I'm about to write is horrible... don't do this at least not without a unique constraint
+    println!("After is main update");
 
-    for t in &mng.titles {
-        sqlx::query!(
-            "INSERT INTO title (title, linked_id, title_id)
-            SELECT * FROM (SELECT ? as title, ? as linked_id , ? as title_id) AS tmp
-            WHERE NOT EXISTS (
-                SELECT title FROM title WHERE title = ? AND linked_id = ?
-            ) LIMIT 1",
-            t.as_str(),
-            mng.linked_id.as_str(),
-            Uuid::new_v4().to_string(),
-            t.as_str(),
-            mng.linked_id.as_str()
-        )
-        .execute(&mut *conn)
-        .await?;
-    }
+    add_titles(
+        mng.id.as_str(),
+        mng.linked_id.as_str(),
+        mng.titles.as_slice(),
+        conn,
+    )
+    .await?;
 
     println!("After title insert");
 
@@ -388,72 +579,51 @@ pub async fn insert_manga(
 
     //first insert into authors table to check if author exists... then do an insert into select statement
 
-    if !mng.artists.is_empty() || !mng.authors.is_empty() {
-        let mut q = QueryBuilder::new("INSERT into author(author_id, name) ");
-
-        q.push_values(
-            mng.artists.iter().chain(mng.authors.iter()),
-            |mut b, author| {
-                b.push_bind(Uuid::new_v4().to_string());
-                b.push_bind(author);
-            },
-        );
-
-        q.push(" ON DUPLICATE KEY update author_id = author_id");
-
-        q.build().execute(&mut *conn).await?;
-    }
+    insert_authors(mng.artists.iter().chain(mng.authors.iter()), conn).await?;
 
     println!("After author insert");
 
     //authors
 
-    if !mng.authors.is_empty() {
-        q = QueryBuilder::new("INSERT into manga_author(manga_id, author_id) select ");
-        q.push_bind(mng.id.as_str());
-        q.push(" as manga_id, author.author_id as author_id from author where author.name IN (");
-
-        let mut sep = q.separated(',');
-
-        for t in &mng.authors {
-            sep.push_bind(t);
-        }
-
-        q.push(')');
-
-        q.build().execute(&mut *conn).await?;
-    }
+    add_manga_authors(
+        mng.id.as_str(),
+        mng.authors.as_slice(),
+        AuthorRole::Author,
+        conn,
+    )
+    .await?;
 
     println!("After manga_author insert");
 
     //artists
 
-    if !mng.artists.is_empty() {
-        q = QueryBuilder::new("INSERT into manga_artist(manga_id, author_id) select ");
-        q.push_bind(mng.id.as_str());
-        q.push(" as manga_id, author.author_id as author_id from author where author.name IN (");
-
-        let mut sep = q.separated(',');
-
-        for t in &mng.artists {
-            sep.push_bind(t);
-        }
-
-        q.push(')');
-
-        q.build().execute(&mut *conn).await?;
-    }
+    add_manga_authors(
+        mng.id.as_str(),
+        mng.artists.as_slice(),
+        AuthorRole::Artist,
+        conn,
+    )
+    .await?;
 
     println!("After manga_artist insert");
 
     //chapters
 
-    for r in &mut mng.chapters {
-        r.chapter_id = Uuid::new_v4().to_string();
-        r.manga_id = mng.id.clone();
-    }
-
-    add_extra_chaps(&mng.chapters, conn).await?;
+    let urls = mng
+        .chapters
+        .iter_mut()
+        .map(|r| claim_chapter(r, mng.id.as_str()))
+        .collect::<Vec<_>>();
+
+    add_extra_chaps(
+        urls.iter()
+            .map(String::as_str)
+            .zip(mng.chapters.iter())
+            .collect::<Vec<_>>()
+            .as_slice(),
+        conn,
+    )
+    .await?;
 
     println!("After chapters insert");
 
@@ -462,60 +632,37 @@ pub async fn insert_manga(
         ", ".to_string(),
     )
     .collect::<String>();
-    let description_small = &mng.description[..255.min(mng.description.len())];
+    let description_small = truncate(mng.description.as_str(), DESCRIPTION_SMALL_LEN);
 
-    sqlx::query!("INSERT into manga_listing(manga_id, cover_url, name, genres, description_small, public_id) VALUES(?, ?, ?, ?, ?, ?)", mng.id, mng.cover_url, mng.name, genres_all, description_small, mng.public_id).execute(&mut *conn).await?;
+    sqlx::query!("INSERT into manga_listing(manga_id, cover_url, name, genres, description_small, public_id, publication_status, cover_retry_at) VALUES(?, ?, ?, ?, ?, ?, ?, ?)", mng.id, mng.cover_url, mng.name, genres_all, description_small, mng.public_id, publication_status.as_str(), Utc::now().timestamp_millis() + QUEUED_GRACE).execute(&mut *conn).await?;
 
     println!("After listing insert");
 
-    println!("Finished inserting {}", mng.url);
+    if let Some(cc) = covers {
+        cc.queue(mng.id.as_str(), mng.cover_url.as_str(), mng.url.as_str());
+    }
 
-    Ok(())
-}
+    log_event(
+        mng.id.as_str(),
+        EventKind::MangaInserted,
+        "",
+        format!("{} ({} chapters) {}", mng.name, mng.chapters.len(), mng.url).as_str(),
+        conn,
+    )
+    .await?;
 
-struct ChapterAndPages {
-    pub chapter_id: String,
-    pub chapter_name: String,
-    pub chapter_number: String,
-    pub updated_at: Option<NaiveDateTime>,
-    pub manga_id: String,
-    pub last_watch_time: i64,
-    pub sequence_number: i32,
+    println!("Finished inserting {}", mng.url);
 
-    pub all_pages: Option<String>,
+    Ok(())
 }
 
+/// Loads the live chapters of a manga in order, each with its current pages.
+///
+/// Chapters and pages come from two queries and are stitched together by the batch loader, so
+/// chapters without pages are kept and page urls go through untouched.
 pub async fn get_chapters(id: &str, conn: &mut PoolConnection<MySql>) -> Result<Vec<ChapterTable>> {
-    //do a hack
-    //use group concat to eliminate multiple sql calls and speed shit up
-    //use space as separator
-
-    let y = sqlx::query_as!(ChapterAndPages, "SELECT chapter.*, group_concat(chapter_page.chapter_page_id, ' ' ,chapter_page.url, ' ', chapter_page.page_number, ' ', chapter_page.chapter_id SEPARATOR ' ') as all_pages from chapter, chapter_page where chapter_page.chapter_id = chapter.chapter_id and chapter.manga_id = ? group by chapter_id order by sequence_number ASC", id).fetch_all(conn).await?;
-
-    Ok(y.into_iter()
-        .map(|f| ChapterTable {
-            chapter_id: f.chapter_id,
-            chapter_name: f.chapter_name,
-            chapter_number: f.chapter_number,
-            last_watch_time: f.last_watch_time,
-            manga_id: f.manga_id,
-            sequence_number: f.sequence_number,
-            updated_at: f.updated_at,
-
-            pages: f
-                .all_pages
-                .unwrap()
-                .split_whitespace()
-                .tuples()
-                .filter_map(|(id, url, pg, ch_id)| {
-                    Some(PageTable {
-                        chapter_id: ch_id.to_string(),
-                        url: url.to_string(),
-                        page_number: str::parse(pg).ok()?,
-                        id: str::parse(id).ok()?,
-                    })
-                })
-                .collect(),
-        })
-        .collect())
+    Ok(load_chapters(&[id], None, conn)
+        .await?
+        .remove(id)
+        .unwrap_or_default())
 }
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use mangaverse_entity::models::{chapter::ChapterTable, page::PageTable};
+use sqlx::{mysql::MySqlRow, pool::PoolConnection, types::chrono::Utc, MySql, QueryBuilder, Row};
+
+use crate::Result;
+
+use super::chapter::MAX_PLACEHOLDERS;
+
+/// A page image stored in a mirror, shared by every page with the same bytes. Each storage keeps
+/// its own copy, so switching storages copies pages again rather than serving the old one.
+#[derive(Debug, Clone)]
+pub struct PageBlob {
+    /// Hex sha256 of the image bytes
+    pub hash: String,
+    /// Name of the storage holding it, such as "local" or "s3"
+    pub storage: String,
+    pub storage_key: String,
+    pub url: String,
+    pub content_type: String,
+}
+
+/// A live page that has not been mirrored yet, with the chapter it was scraped from to use as
+/// the referer, since most image hosts refuse requests without one
+#[derive(Debug, Clone)]
+pub struct PendingPage {
+    pub page: PageTable,
+    pub referer: Option<String>,
+}
+
+fn pending_pages(rows: Vec<MySqlRow>) -> Result<Vec<PendingPage>> {
+    let mut out = Vec::with_capacity(rows.len());
+
+    for r in rows {
+        out.push(PendingPage {
+            page: PageTable {
+                id: r.try_get("chapter_page_id")?,
+                url: r.try_get("url")?,
+                page_number: r.try_get("page_number")?,
+                chapter_id: r.try_get("chapter_id")?,
+            },
+            referer: r.try_get("source_url")?,
+        });
+    }
+
+    Ok(out)
+}
+
+/// Oldest pages not mirrored into `storage` first, skipping those that already failed
+/// `max_attempts` times
+pub async fn get_unmirrored_pages(
+    limit: u32,
+    max_attempts: i32,
+    storage: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<PendingPage>> {
+    let rows = sqlx::query("SELECT chapter_page.chapter_page_id, chapter_page.url, chapter_page.page_number, chapter_page.chapter_id, chapter.source_url from chapter_page, chapter where chapter_page.chapter_id = chapter.chapter_id and chapter.removed_at is null and chapter_page.removed_at is null and (chapter_page.content_hash is null or NOT EXISTS (SELECT page_blob.hash from page_blob where page_blob.hash = chapter_page.content_hash and page_blob.storage = ?)) and chapter_page.mirror_attempts < ? order by chapter_page.chapter_page_id ASC limit ?")
+        .bind(storage)
+        .bind(max_attempts)
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    pending_pages(rows)
+}
+
+/// Oldest pages that were never inspected first
+pub async fn get_uninspected_pages(
+    limit: u32,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<PendingPage>> {
+    let rows = sqlx::query("SELECT chapter_page.chapter_page_id, chapter_page.url, chapter_page.page_number, chapter_page.chapter_id, chapter.source_url from chapter_page, chapter where chapter_page.chapter_id = chapter.chapter_id and chapter.removed_at is null and chapter_page.removed_at is null and chapter_page.inspected_at is null order by chapter_page.chapter_page_id ASC limit ?")
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    pending_pages(rows)
+}
+
+pub async fn get_blob(
+    hash: &str,
+    storage: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Option<PageBlob>> {
+    Ok(sqlx::query_as!(
+        PageBlob,
+        "SELECT hash, storage, storage_key, url, content_type from page_blob where hash = ? and storage = ?",
+        hash,
+        storage
+    )
+    .fetch_optional(&mut *conn)
+    .await?)
+}
+
+/// Records a stored blob. Two workers storing the same bytes at once is harmless, the second
+/// insert is ignored.
+pub async fn insert_blob(blob: &PageBlob, conn: &mut PoolConnection<MySql>) -> Result<()> {
+    sqlx::query!(
+        "INSERT IGNORE into page_blob(hash, storage, storage_key, url, content_type, created_at) values (?, ?, ?, ?, ?, ?)",
+        blob.hash,
+        blob.storage,
+        blob.storage_key,
+        blob.url,
+        blob.content_type,
+        Utc::now().timestamp_millis()
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks a page mirrored. The blob it is served from is the one keyed by its `content_hash`, which
+/// `set_page_metadata` records.
+pub async fn set_page_mirrored(page: &PageTable, conn: &mut PoolConnection<MySql>) -> Result<()> {
+    sqlx::query(
+        "UPDATE chapter_page SET mirrored_at = ?, mirror_error = null where chapter_page_id = ?",
+    )
+    .bind(Utc::now().timestamp_millis())
+    .bind(&page.id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_page_mirror_failed(
+    page: &PageTable,
+    error: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    sqlx::query("UPDATE chapter_page SET mirror_attempts = mirror_attempts + 1, mirror_error = left(?, 1024) where chapter_page_id = ?")
+        .bind(error)
+        .bind(&page.id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// What a fetched page image turned out to be
+#[derive(Debug, Clone, Default)]
+pub struct PageMetadata {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub bytes: u64,
+    /// None when the bytes are not an image we recognise
+    pub mime_type: Option<String>,
+    /// Hex sha256 of the bytes, also the key of the page's mirrored blobs
+    pub content_hash: String,
+}
+
+pub async fn set_page_metadata(
+    page: &PageTable,
+    meta: &PageMetadata,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    sqlx::query("UPDATE chapter_page SET width = ?, height = ?, bytes = ?, mime_type = ?, content_hash = ?, inspected_at = ?, inspect_error = null where chapter_page_id = ?")
+        .bind(meta.width)
+        .bind(meta.height)
+        .bind(meta.bytes)
+        .bind(meta.mime_type.as_deref())
+        .bind(meta.content_hash.as_str())
+        .bind(Utc::now().timestamp_millis())
+        .bind(&page.id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Marks a page inspected without metadata, so a dead link is reported instead of retried forever
+pub async fn set_page_inspect_failed(
+    page: &PageTable,
+    error: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    sqlx::query("UPDATE chapter_page SET inspected_at = ?, inspect_error = left(?, 1024) where chapter_page_id = ?")
+        .bind(Utc::now().timestamp_millis())
+        .bind(error)
+        .bind(&page.id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Size and type of a page, for readers to lay out a chapter before its images arrive
+#[derive(Debug, Clone)]
+pub struct PageInfo {
+    pub page_number: i32,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub mime_type: Option<String>,
+}
+
+pub async fn get_page_info(
+    chapter_id: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<PageInfo>> {
+    Ok(sqlx::query_as!(
+        PageInfo,
+        "SELECT page_number, width, height, mime_type from chapter_page where chapter_id = ? and removed_at is null order by page_number ASC",
+        chapter_id
+    )
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// A live page whose image looks wrong
+#[derive(Debug, Clone)]
+pub struct PageIssue {
+    pub chapter_id: String,
+    pub page_number: i32,
+    pub url: String,
+    /// Why the fetch failed, if it did
+    pub inspect_error: Option<String>,
+    pub bytes: Option<u64>,
+    pub mime_type: Option<String>,
+    /// Other live pages of the same manga with identical bytes, such as a repeated placeholder
+    pub duplicates: i64,
+}
+
+/// Inspected pages of a manga that failed to load, are empty, are not images or share their bytes
+/// with another page
+pub async fn get_page_issues(
+    manga_id: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<Vec<PageIssue>> {
+    Ok(sqlx::query_as!(
+        PageIssue,
+        "SELECT x.chapter_id as `chapter_id!`, x.page_number as `page_number!`, x.url as `url!`, x.inspect_error, x.bytes, x.mime_type, x.duplicates as `duplicates!: i64` from (SELECT p.chapter_id, p.page_number, p.url, p.inspect_error, p.bytes, p.mime_type, c.sequence_number, (SELECT count(*) from chapter_page d, chapter dc where d.chapter_id = dc.chapter_id and dc.manga_id = c.manga_id and dc.removed_at is null and d.removed_at is null and d.content_hash = p.content_hash and d.chapter_page_id != p.chapter_page_id) as duplicates from chapter_page p, chapter c where p.chapter_id = c.chapter_id and c.manga_id = ? and c.removed_at is null and p.removed_at is null and p.inspected_at is not null) x where x.inspect_error is not null or x.bytes = 0 or x.mime_type is null or x.duplicates > 0 order by x.sequence_number ASC, x.page_number ASC",
+        manga_id
+    )
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// Points the pages of already loaded chapters at their copies in `storage` where one exists.
+///
+/// Meant for code serving readers, which `get_mangas_from_ids_mirrored` does for them. Refreshes
+/// must keep comparing against the source urls, so `get_chapters` and the other batch loaders
+/// leave pages alone.
+pub async fn prefer_mirrors(
+    chapters: &mut [ChapterTable],
+    storage: &str,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    let mut mirrored: HashMap<(String, i32), String> = HashMap::new();
+
+    for chunk in chapters.chunks(MAX_PLACEHOLDERS - 1) {
+        let mut q = QueryBuilder::new("SELECT chapter_page.chapter_id, chapter_page.page_number, page_blob.url from chapter_page, page_blob where chapter_page.content_hash = page_blob.hash and page_blob.storage = ");
+        q.push_bind(storage);
+        q.push(" and chapter_page.removed_at is null and chapter_page.chapter_id IN (");
+        let mut s = q.separated(", ");
+        for c in chunk {
+            s.push_bind(c.chapter_id.as_str());
+        }
+        q.push(")");
+
+        for r in q.build().fetch_all(&mut *conn).await? {
+            mirrored.insert(
+                (r.try_get("chapter_id")?, r.try_get("page_number")?),
+                r.try_get("url")?,
+            );
+        }
+    }
+
+    for c in chapters.iter_mut() {
+        for p in c.pages.iter_mut() {
+            if let Some(u) = mirrored.remove(&(c.chapter_id.clone(), p.page_number)) {
+                p.url = u;
+            }
+        }
+    }
+
+    Ok(())
+}
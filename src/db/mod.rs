@@ -1,4 +1,13 @@
+pub mod author;
+pub mod batch;
 pub mod chapter;
+pub mod cover;
+pub mod diff;
+pub mod event;
 pub mod genre;
+pub mod guard;
+pub mod link;
 pub mod manga;
+pub mod mirror;
 pub mod source;
+pub mod title;
@@ -1,4 +1,7 @@
 use mangaverse_entity::models::source::SourceTable;
+use sqlx::mysql::MySqlConnection;
+use sqlx::pool::PoolConnection;
+use sqlx::Acquire;
 use sqlx::MySql;
 use sqlx::Pool;
 use uuid::Uuid;
@@ -6,6 +9,8 @@ use uuid::Uuid;
 use crate::MSError;
 use crate::Result;
 
+use super::link::elect_main;
+
 pub async fn insert_source_if_not_exists(
     src_name: &str,
     pri: i32,
@@ -40,3 +45,79 @@ pub async fn insert_source_if_not_exists(
         Ok(y)
     }
 }
+
+/// Changes the priority of a source and re-elects the main manga of every group it is part of, in
+/// one transaction. `src` only takes the new priority once it is committed.
+pub async fn set_source_priority(
+    src: &mut SourceTable,
+    pri: i32,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    let mut txt = conn.begin().await?;
+
+    sqlx::query!(
+        "UPDATE source SET priority = ? where source_id = ?",
+        pri,
+        src.id.as_str()
+    )
+    .execute(&mut txt)
+    .await?;
+
+    reelect_source_groups(src.id.as_str(), &mut txt).await?;
+
+    txt.commit().await?;
+
+    src.priority = pri;
+
+    Ok(())
+}
+
+/// Enables or disables a source. Manga from a disabled source stay, but lose main status to any
+/// other source in their group.
+pub async fn set_source_enabled(
+    source_id: &str,
+    enabled: bool,
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    let mut txt = conn.begin().await?;
+
+    sqlx::query!(
+        "UPDATE source SET is_enabled = ? where source_id = ?",
+        enabled,
+        source_id
+    )
+    .execute(&mut txt)
+    .await?;
+
+    reelect_source_groups(source_id, &mut txt).await?;
+
+    txt.commit().await?;
+
+    Ok(())
+}
+
+pub async fn is_source_enabled(source_id: &str, conn: &mut PoolConnection<MySql>) -> Result<bool> {
+    Ok(sqlx::query!(
+        "SELECT is_enabled as `data: bool` from source where source_id = ?",
+        source_id
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .data)
+}
+
+/// Re-elects the main manga of every group the source is part of
+async fn reelect_source_groups(source_id: &str, conn: &mut MySqlConnection) -> Result<()> {
+    let groups = sqlx::query!(
+        "SELECT DISTINCT linked_id as data from manga where source_id = ?",
+        source_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for g in groups {
+        elect_main(g.data.as_str(), conn).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use itertools::Itertools;
+use sqlx::{pool::PoolConnection, MySql, MySqlConnection};
+use uuid::Uuid;
+
+use crate::linker::normalize::normalize_title;
+use crate::parse::text::clean_line;
+use crate::Result;
+
+/// Cleans up titles and drops blank and repeated ones
+pub fn clean_titles(titles: &mut Vec<String>) {
+    *titles = titles
+        .iter()
+        .map(|f| clean_line(f))
+        .filter(|f| !f.is_empty())
+        .unique()
+        .collect();
+}
+
+/// Titles this manga contributed to its group
+pub async fn get_claimed_titles(manga_id: &str, conn: &mut MySqlConnection) -> Result<Vec<String>> {
+    Ok(sqlx::query!(
+        "SELECT title as data from manga_title where manga_id = ?",
+        manga_id
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|f| f.data)
+    .collect())
+}
+
+/// Records the titles against the manga and adds the ones its group doesn't have yet
+pub async fn add_titles(
+    manga_id: &str,
+    linked_id: &str,
+    titles: &[String],
+    conn: &mut MySqlConnection,
+) -> Result<()> {
+    //what I'm about to write is horrible... don't do this at least not without a unique constraint
+
+    for t in titles {
+        sqlx::query!(
+            "INSERT INTO title (title, linked_id, title_id, normalized_title)
+            SELECT * FROM (SELECT ? as title, ? as linked_id , ? as title_id, ? as normalized_title) AS tmp
+            WHERE NOT EXISTS (
+                SELECT title FROM title WHERE title = ? AND linked_id = ?
+            ) LIMIT 1",
+            t.as_str(),
+            linked_id,
+            Uuid::new_v4().to_string(),
+            normalize_title(t),
+            t.as_str(),
+            linked_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "INSERT IGNORE INTO manga_title(manga_id, title) VALUES(?, ?)",
+            manga_id,
+            t.as_str()
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Drops the manga's claim on the titles, and the titles themselves once no manga in the group
+/// lists them anymore
+pub async fn remove_titles(
+    manga_id: &str,
+    linked_id: &str,
+    titles: &[String],
+    conn: &mut PoolConnection<MySql>,
+) -> Result<()> {
+    for t in titles {
+        sqlx::query!(
+            "DELETE FROM manga_title where manga_id = ? and title = ?",
+            manga_id,
+            t.as_str()
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "DELETE FROM title where title = ? and linked_id = ? and NOT EXISTS (
+                SELECT manga_title.title FROM manga_title, manga
+                WHERE manga_title.manga_id = manga.manga_id AND manga.linked_id = ? AND manga_title.title = ?
+            )",
+            t.as_str(),
+            linked_id,
+            linked_id,
+            t.as_str()
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
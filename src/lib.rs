@@ -1,12 +1,17 @@
 use std::collections::HashMap;
 
 // use crate::db::{genre::insert_genre, manga::{get_manga, update_manga}};
+use db::genre::GenreMap;
 use mangaverse_entity::models::{genre::Genre, source::SourceTable};
 // use sqlx::mysql::MySqlPoolOptions;
 
 pub mod db;
+pub mod linker;
 pub mod mangadino;
 pub mod manganelo;
+pub mod mirror;
+pub mod notify;
+pub mod parse;
 pub mod readm;
 pub mod studygroup;
 
@@ -65,7 +70,10 @@ impl From<std::io::Error> for MSError {
 #[derive(Default, Debug)]
 pub struct Context {
     pub sources: HashMap<String, SourceTable>,
+    /// Canonical genres by name
     pub genres: HashMap<String, Genre>,
+    /// How each source's genre names resolve, by source name
+    pub genre_maps: HashMap<String, GenreMap>,
 }
 
 // async fn setup_db() -> Result<sqlx::Pool<sqlx::MySql>> {
@@ -0,0 +1,385 @@
+use std::collections::HashSet;
+
+use mangaverse_entity::models::manga::MangaTable;
+
+pub mod normalize;
+
+use normalize::{normalize_title, title_similarity};
+
+/// Titles at least this similar are reported as evidence for a link
+const TITLE_EVIDENCE: f64 = 0.8;
+
+/// How candidate groups are scored when linking a new manga.
+#[derive(Debug, Clone)]
+pub struct LinkConfig {
+    /// Link automatically when the best group scores at least this much
+    pub threshold: f64,
+    /// Groups scoring at least this much, but below `threshold`, are queued for review
+    pub review_threshold: f64,
+    /// Groups scoring within this much of a linkable best one are treated as a conflict, even
+    /// when they fall short of `threshold` themselves
+    pub conflict_margin: f64,
+    pub title_weight: f64,
+    pub author_weight: f64,
+    pub chapter_weight: f64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 0.85,
+            review_threshold: 0.6,
+            conflict_margin: 0.05,
+            title_weight: 0.6,
+            author_weight: 0.3,
+            chapter_weight: 0.1,
+        }
+    }
+}
+
+/// What is known about an existing `linked_id` group
+#[derive(Debug, Clone, Default)]
+pub struct GroupEvidence {
+    pub linked_id: String,
+    pub titles: Vec<String>,
+    /// Authors and artists of every manga in the group
+    pub authors: Vec<String>,
+    /// Chapters of the group's main manga
+    pub chapter_count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkScore {
+    pub linked_id: String,
+    pub score: f64,
+    pub title_score: f64,
+    pub author_score: Option<f64>,
+    pub chapter_score: Option<f64>,
+    pub matching_titles: Vec<String>,
+    pub matching_authors: Vec<String>,
+}
+
+fn people(names: impl Iterator<Item = impl AsRef<str>>) -> HashSet<String> {
+    names
+        .map(|f| f.as_ref().trim().to_uppercase())
+        .filter(|f| !f.is_empty())
+        .collect()
+}
+
+/// Scores how likely it is that `mng` belongs to the group.
+///
+/// Author and chapter evidence only count when both sides have some; their weight is otherwise
+/// left out rather than counted against the match.
+pub fn score_group(mng: &MangaTable<'_>, group: &GroupEvidence, cfg: &LinkConfig) -> LinkScore {
+    let ours: Vec<String> = mng.titles.iter().map(|f| normalize_title(f)).collect();
+
+    let mut title_score: f64 = 0.0;
+    let mut matching_titles = Vec::new();
+
+    for t in &group.titles {
+        let n = normalize_title(t);
+        let best = ours
+            .iter()
+            .map(|f| title_similarity(f, n.as_str()))
+            .fold(0.0, f64::max);
+        if best >= TITLE_EVIDENCE {
+            matching_titles.push(t.clone());
+        }
+        title_score = title_score.max(best);
+    }
+
+    let a = people(mng.authors.iter().chain(mng.artists.iter()));
+    let b = people(group.authors.iter());
+
+    let matching_authors: Vec<String> = a.intersection(&b).cloned().collect();
+
+    let author_score = if a.is_empty() || b.is_empty() {
+        None
+    } else {
+        Some(matching_authors.len() as f64 / a.len().min(b.len()) as f64)
+    };
+
+    let chapter_score = if mng.chapters.is_empty() || group.chapter_count == 0 {
+        None
+    } else {
+        let (x, y) = (mng.chapters.len(), group.chapter_count);
+        Some(x.min(y) as f64 / x.max(y) as f64)
+    };
+
+    let mut total = title_score * cfg.title_weight;
+    let mut weight = cfg.title_weight;
+
+    if let Some(s) = author_score {
+        total += s * cfg.author_weight;
+        weight += cfg.author_weight;
+    }
+
+    if let Some(s) = chapter_score {
+        total += s * cfg.chapter_weight;
+        weight += cfg.chapter_weight;
+    }
+
+    LinkScore {
+        linked_id: group.linked_id.clone(),
+        score: if weight > 0.0 { total / weight } else { 0.0 },
+        title_score,
+        author_score,
+        chapter_score,
+        matching_titles,
+        matching_authors,
+    }
+}
+
+/// Scores every group, best first. Equal scores are ranked by how many titles and then how many
+/// authors match.
+pub fn rank_groups(
+    mng: &MangaTable<'_>,
+    groups: &[GroupEvidence],
+    cfg: &LinkConfig,
+) -> Vec<LinkScore> {
+    let mut scores: Vec<LinkScore> = groups.iter().map(|g| score_group(mng, g, cfg)).collect();
+    scores.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(b.matching_titles.len().cmp(&a.matching_titles.len()))
+            .then(b.matching_authors.len().cmp(&a.matching_authors.len()))
+    });
+    scores
+}
+
+/// What to do with a new manga given its ranked candidate groups
+#[derive(Debug, Clone)]
+pub enum LinkDecision {
+    /// One group clearly matches
+    Link(LinkScore),
+    /// Several groups match about equally well, a human has to pick
+    Conflict(Vec<LinkScore>),
+    /// Some groups might match, but none well enough to link on its own
+    Review(Vec<LinkScore>),
+    /// Nothing comes close
+    Unmatched,
+}
+
+/// Links to the best group only when no runner-up comes within `conflict_margin` of it
+pub fn decide_link(ranked: Vec<LinkScore>, cfg: &LinkConfig) -> LinkDecision {
+    let best = match ranked.first() {
+        Some(b) => b.score,
+        None => return LinkDecision::Unmatched,
+    };
+
+    if best >= cfg.threshold {
+        let mut close: Vec<LinkScore> = ranked
+            .into_iter()
+            .take_while(|f| best - f.score <= cfg.conflict_margin)
+            .collect();
+
+        if close.len() > 1 {
+            LinkDecision::Conflict(close)
+        } else {
+            LinkDecision::Link(close.remove(0))
+        }
+    } else {
+        let maybe: Vec<LinkScore> = ranked
+            .into_iter()
+            .filter(|f| f.score >= cfg.review_threshold)
+            .collect();
+
+        if maybe.is_empty() {
+            LinkDecision::Unmatched
+        } else {
+            LinkDecision::Review(maybe)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mangaverse_entity::models::{chapter::ChapterTable, source::SourceTable};
+
+    use super::*;
+
+    static SOURCE: SourceTable = SourceTable {
+        id: String::new(),
+        name: String::new(),
+        priority: 0,
+    };
+
+    fn manga(chapters: usize) -> MangaTable<'static> {
+        MangaTable {
+            id: "m1".to_string(),
+            linked_id: "l1".to_string(),
+            is_listed: true,
+            name: "Solo Leveling".to_string(),
+            cover_url: String::new(),
+            url: "https://example.com/solo-leveling".to_string(),
+            last_updated: None,
+            status: "Ongoing".to_string(),
+            is_main: true,
+            description: String::new(),
+            last_watch_time: None,
+            public_id: "p1".to_string(),
+            is_old: false,
+            source: &SOURCE,
+            chapters: (0..chapters)
+                .map(|i| ChapterTable {
+                    sequence_number: i as i32,
+                    ..Default::default()
+                })
+                .collect(),
+            authors: vec!["Chugong".to_string()],
+            artists: vec!["Dubu".to_string()],
+            genres: Vec::new(),
+            titles: vec![
+                "Solo Leveling".to_string(),
+                "Na Honjaman Level Up".to_string(),
+            ],
+        }
+    }
+
+    fn group(linked_id: &str, titles: &[&str], authors: &[&str], chapters: usize) -> GroupEvidence {
+        GroupEvidence {
+            linked_id: linked_id.to_string(),
+            titles: titles.iter().map(|f| f.to_string()).collect(),
+            authors: authors.iter().map(|f| f.to_string()).collect(),
+            chapter_count: chapters,
+        }
+    }
+
+    fn score(linked_id: &str, score: f64) -> LinkScore {
+        LinkScore {
+            linked_id: linked_id.to_string(),
+            score,
+            title_score: score,
+            author_score: None,
+            chapter_score: None,
+            matching_titles: Vec::new(),
+            matching_authors: Vec::new(),
+        }
+    }
+
+    fn ids(scores: &[LinkScore]) -> Vec<&str> {
+        scores.iter().map(|f| f.linked_id.as_str()).collect()
+    }
+
+    #[test]
+    fn matching_group_scores_full_marks() {
+        let s = score_group(
+            &manga(100),
+            &group(
+                "a",
+                &["Solo Leveling (Official)"],
+                &["CHUGONG", "dubu "],
+                100,
+            ),
+            &LinkConfig::default(),
+        );
+        assert!((s.score - 1.0).abs() < 1e-9);
+        assert_eq!(s.matching_titles, vec!["Solo Leveling (Official)"]);
+        assert_eq!(s.matching_authors.len(), 2);
+        assert_eq!(s.chapter_score, Some(1.0));
+    }
+
+    #[test]
+    fn missing_evidence_is_left_out() {
+        let s = score_group(
+            &manga(0),
+            &group("a", &["Solo Leveling"], &[], 50),
+            &LinkConfig::default(),
+        );
+        assert_eq!(s.author_score, None);
+        assert_eq!(s.chapter_score, None);
+        assert!((s.score - s.title_score).abs() < 1e-9);
+    }
+
+    #[test]
+    fn disagreeing_evidence_lowers_the_score() {
+        let cfg = LinkConfig::default();
+        let s = score_group(
+            &manga(100),
+            &group("a", &["Solo Leveling"], &["Someone Else"], 25),
+            &cfg,
+        );
+        assert_eq!(s.author_score, Some(0.0));
+        assert_eq!(s.chapter_score, Some(0.25));
+        assert!(s.matching_authors.is_empty());
+
+        let expected = (cfg.title_weight + 0.25 * cfg.chapter_weight)
+            / (cfg.title_weight + cfg.author_weight + cfg.chapter_weight);
+        assert!((s.score - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unrelated_titles_are_not_evidence() {
+        let s = score_group(
+            &manga(0),
+            &group("a", &["One Piece"], &[], 0),
+            &LinkConfig::default(),
+        );
+        assert!(s.matching_titles.is_empty());
+        assert!(s.score < TITLE_EVIDENCE);
+    }
+
+    #[test]
+    fn ranks_best_first_then_by_matching_titles_and_authors() {
+        let groups = [
+            group("unrelated", &["One Piece"], &[], 0),
+            group("one_title", &["Solo Leveling"], &["Chugong"], 0),
+            group(
+                "two_titles",
+                &["Solo Leveling", "Na Honjaman Level Up"],
+                &["Chugong"],
+                0,
+            ),
+            group("two_authors", &["Solo Leveling"], &["Chugong", "Dubu"], 0),
+        ];
+
+        let ranked = rank_groups(&manga(0), &groups, &LinkConfig::default());
+        assert_eq!(
+            ids(&ranked),
+            vec!["two_titles", "two_authors", "one_title", "unrelated"]
+        );
+    }
+
+    #[test]
+    fn links_a_clear_winner() {
+        let cfg = LinkConfig::default();
+        match decide_link(vec![score("a", 0.95), score("b", 0.7)], &cfg) {
+            LinkDecision::Link(l) => assert_eq!(l.linked_id, "a"),
+            d => panic!("expected a link, got {:?}", d),
+        }
+    }
+
+    #[test]
+    fn runner_up_below_threshold_still_conflicts() {
+        let cfg = LinkConfig::default();
+        match decide_link(vec![score("a", 0.86), score("b", 0.84)], &cfg) {
+            LinkDecision::Conflict(c) => assert_eq!(ids(&c), vec!["a", "b"]),
+            d => panic!("expected a conflict, got {:?}", d),
+        }
+    }
+
+    #[test]
+    fn below_threshold_goes_to_review() {
+        let cfg = LinkConfig::default();
+        match decide_link(
+            vec![score("a", 0.8), score("b", 0.65), score("c", 0.3)],
+            &cfg,
+        ) {
+            LinkDecision::Review(r) => assert_eq!(ids(&r), vec!["a", "b"]),
+            d => panic!("expected a review, got {:?}", d),
+        }
+    }
+
+    #[test]
+    fn nothing_close_is_unmatched() {
+        let cfg = LinkConfig::default();
+        assert!(matches!(
+            decide_link(Vec::new(), &cfg),
+            LinkDecision::Unmatched
+        ));
+        assert!(matches!(
+            decide_link(vec![score("a", 0.2)], &cfg),
+            LinkDecision::Unmatched
+        ));
+    }
+}
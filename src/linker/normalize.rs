@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+
+const NOTES: [&str; 6] = ["official", "webtoon", "manhwa", "manhua", "manga", "novel"];
+
+fn fold_char(c: char) -> char {
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' => 'a',
+        'è' | 'é' | 'ê' | 'ë' | 'ē' => 'e',
+        'ì' | 'í' | 'î' | 'ï' | 'ī' => 'i',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' => 'o',
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' => 'u',
+        'ñ' => 'n',
+        'ç' => 'c',
+        'ý' | 'ÿ' => 'y',
+        _ => c,
+    }
+}
+
+/// Spells long vowels the short way so "Shoujo", "Shōjo" and "Shojo" agree
+fn fold_romanization(token: &str) -> String {
+    token
+        .replace("ou", "o")
+        .replace("oo", "o")
+        .replace("uu", "u")
+}
+
+fn strip_brackets(t: &str) -> String {
+    let mut out = String::with_capacity(t.len());
+    let mut depth = 0usize;
+    for c in t.chars() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Reduces a title to a comparable form: lowercase, no accents, punctuation, bracketed notes,
+/// leading article or trailing "official"-style notes, and romanization folded.
+pub fn normalize_title(title: &str) -> String {
+    let lower = title.to_lowercase();
+
+    let stripped = strip_brackets(lower.as_str());
+    let base = if stripped.trim().is_empty() {
+        lower.as_str()
+    } else {
+        stripped.as_str()
+    };
+
+    let cleaned: String = base
+        .chars()
+        .map(fold_char)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+
+    let mut tokens: Vec<&str> = cleaned.split_whitespace().collect();
+
+    if tokens.len() > 1 && tokens[0] == "the" {
+        tokens.remove(0);
+    }
+
+    while tokens.len() > 1 && tokens.last().is_some_and(|f| NOTES.contains(f)) {
+        tokens.pop();
+    }
+
+    tokens
+        .into_iter()
+        .map(fold_romanization)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let sub = prev[j] + usize::from(ca != *cb);
+            cur[j + 1] = sub.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()]
+}
+
+/// Similarity of two already normalized titles between 0 and 1
+pub fn title_similarity(a: &str, b: &str) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    if a == b {
+        return 1.0;
+    }
+
+    let ta: HashSet<&str> = a.split(' ').collect();
+    let tb: HashSet<&str> = b.split(' ').collect();
+    let jaccard = ta.intersection(&tb).count() as f64 / ta.union(&tb).count() as f64;
+
+    let len = a.chars().count().max(b.chars().count());
+    let edit = 1.0 - levenshtein(a, b) as f64 / len as f64;
+
+    jaccard.max(edit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_case_accents_and_punctuation() {
+        assert_eq!(normalize_title("Shōjo Café!"), "shojo cafe");
+        assert_eq!(
+            normalize_title("Kaguya-sama: Love Is War"),
+            "kaguya sama love is war"
+        );
+    }
+
+    #[test]
+    fn drops_brackets_article_and_notes() {
+        assert_eq!(
+            normalize_title("The Beginning After The End"),
+            "beginning after the end"
+        );
+        assert_eq!(normalize_title("Solo Leveling (Official)"), "solo leveling");
+        assert_eq!(normalize_title("Tower of God [Webtoon]"), "tower of god");
+        assert_eq!(
+            normalize_title("Omniscient Reader Manhwa"),
+            "omniscient reader"
+        );
+    }
+
+    #[test]
+    fn keeps_titles_that_are_only_notes() {
+        assert_eq!(normalize_title("(Manga)"), "manga");
+        assert_eq!(normalize_title("The"), "the");
+        assert_eq!(normalize_title("Manga"), "manga");
+    }
+
+    #[test]
+    fn folds_romanization() {
+        assert_eq!(normalize_title("Shoujo"), normalize_title("Shōjo"));
+        assert_eq!(normalize_title("Oosama Ranking"), "osama ranking");
+        assert_eq!(normalize_title("Kuuga"), "kuga");
+    }
+
+    #[test]
+    fn similarity_bounds() {
+        assert_eq!(title_similarity("one piece", "one piece"), 1.0);
+        assert_eq!(title_similarity("", "one piece"), 0.0);
+        assert_eq!(title_similarity("one piece", ""), 0.0);
+        assert!(title_similarity("one piece", "naruto") < 0.5);
+    }
+
+    #[test]
+    fn similarity_tolerates_typos_and_word_order() {
+        assert!(title_similarity("solo leveling", "solo levelling") > 0.9);
+        assert_eq!(title_similarity("love is war", "war is love"), 1.0);
+        assert!(title_similarity("tower of god", "tower of god season 2") > 0.5);
+    }
+}
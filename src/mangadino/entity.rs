@@ -1,11 +1,12 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 use lazy_static::lazy_static;
-use mangaverse_entity::models::{genre::Genre, manga::MangaTable, source::SourceTable};
+use mangaverse_entity::models::{manga::MangaTable, source::SourceTable};
 use scraper::{Html, Selector};
 use sqlx::{MySql, Pool};
 
-use crate::{db::source::insert_source_if_not_exists, MSError, Result};
+use crate::parse::text::{clean_line, html_to_text};
+use crate::{db::genre::GenreMap, db::source::insert_source_if_not_exists, MSError, Result};
 
 const SOURCE_NAME: &str = "mangadino";
 
@@ -27,38 +28,58 @@ lazy_static! {
         Selector::parse("div.container-chapter-reader > img").unwrap();
 }
 
-pub async fn get_mangadino_genres() -> Result<HashSet<String>> {
+/// Genre names offered by the site along with the page listing each genre
+pub async fn get_mangadino_genres() -> Result<HashMap<String, Option<String>>> {
     let url = "https://mangadino.com/action/";
 
     let response_text = reqwest::get(url).await?.text().await?;
 
-    let doc = Html::parse_document(&response_text);
+    Ok(parse_genres(&Html::parse_document(&response_text)))
+}
 
-    Ok(doc
-        .select(&GENRE_SELECTOR)
+pub fn parse_genres(doc: &Html) -> HashMap<String, Option<String>> {
+    doc.select(&GENRE_SELECTOR)
         .skip(1)
-        .map(|f| f.text().collect::<String>().trim().to_lowercase())
-        .collect())
+        .map(|f| {
+            (
+                f.text().collect::<String>().trim().to_lowercase(),
+                f.value()
+                    .attr("value")
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .map(|v| format!("https://mangadino.com/{}/", v)),
+            )
+        })
+        .collect()
 }
 
 pub async fn get_mangadino_source(pool: &Pool<MySql>) -> Result<SourceTable> {
     insert_source_if_not_exists(SOURCE_NAME, 3, pool).await
 }
 
-#[allow(unused_must_use)]
 pub async fn get_manga<'a>(
     url: String,
     sc: &'a SourceTable,
-    map: &'a HashMap<String, Genre>,
+    map: &'a GenreMap,
+) -> Result<MangaTable<'a>> {
+    let text = reqwest::get(url.as_str()).await?.text().await?;
+
+    parse_manga(&Html::parse_document(text.as_str()), url, sc, map)
+}
+
+/// Reads a manga page. Chapters are not read yet, so this always ends in an error rather than
+/// handing back a manga that looks like it lost all its chapters.
+pub fn parse_manga<'a>(
+    doc: &Html,
+    url: String,
+    sc: &'a SourceTable,
+    map: &'a GenreMap,
 ) -> Result<MangaTable<'a>> {
     let mut mng: MangaTable = MangaTable::new(sc);
     mng.is_listed = true;
     mng.url = url;
 
     {
-        let doc =
-            Html::parse_document(reqwest::get(mng.url.as_str()).await?.text().await?.as_str());
-
         mng.name.extend(
             doc.select(&NAME_SELECTOR)
                 .next()
@@ -69,7 +90,7 @@ pub async fn get_manga<'a>(
                 .text(),
         );
 
-        mng.name = mng.name.trim().to_string();
+        mng.name = clean_line(mng.name.as_str());
 
         mng.titles.push(mng.name.clone());
 
@@ -93,19 +114,14 @@ pub async fn get_manga<'a>(
             for t in in_sel.by_ref() {
                 let mut rt = t.select(&METADATA_VALUE_SELECTOR);
 
-                let key = rt.next();
-                let val = rt.next();
-
-                if key.is_none() || val.is_none() {
-                    continue;
-                }
-
-                let key = key.unwrap();
-                let val = val.unwrap();
+                let (key, val) = match (rt.next(), rt.next()) {
+                    (Some(k), Some(v)) => (k, v),
+                    _ => continue,
+                };
 
                 match key {
                     x if x.inner_html().to_lowercase() == "alternative" => {
-                        let act_val = val.inner_html();
+                        let act_val = clean_line(html_to_text(val.inner_html().as_str()).as_str());
                         if act_val == "-" {
                             continue;
                         }
@@ -113,32 +129,35 @@ pub async fn get_manga<'a>(
                             .extend(act_val.split(&[';']).map(|f| f.to_string()))
                     }
                     x if x.inner_html().to_lowercase() == "author" => {
-                        let act_val = val.inner_html();
+                        let act_val = clean_line(html_to_text(val.inner_html().as_str()).as_str());
                         if act_val == "-" {
                             continue;
                         }
                         mng.authors = act_val.split(&[',']).map(|f| f.to_string()).collect();
                     }
                     x if x.inner_html().to_lowercase() == "artist" => {
-                        let act_val = val.inner_html();
+                        let act_val = clean_line(html_to_text(val.inner_html().as_str()).as_str());
                         if act_val == "-" {
                             continue;
                         }
                         mng.artists = act_val.split(&[',']).map(|f| f.to_string()).collect();
                     }
                     x if x.inner_html().to_lowercase() == "genre" => {
-                        let act_val = val.inner_html();
+                        let act_val = clean_line(html_to_text(val.inner_html().as_str()).as_str());
                         if act_val == "-" {
                             continue;
                         }
                         mng.genres = val
                             .select(&MANGA_GENRE_SELECTOR)
-                            .map(|f| f.inner_html().trim().to_lowercase())
+                            .map(|f| {
+                                clean_line(html_to_text(f.inner_html().as_str()).as_str())
+                                    .to_lowercase()
+                            })
                             .filter_map(|f| map.get(f.as_str()))
                             .collect();
                     }
                     x if x.inner_html().to_lowercase() == "status" => {
-                        let act_val = val.inner_html();
+                        let act_val = clean_line(html_to_text(val.inner_html().as_str()).as_str());
                         if act_val == "-" {
                             continue;
                         }
@@ -215,7 +234,10 @@ pub async fn get_manga<'a>(
     //     }
     // }
 
-    todo!()
+    Err(MSError {
+        message: format!("Chapters of {} can't be read yet", mng.url),
+        err_type: crate::MSErrorType::OtherError,
+    })
 
     // Ok(mng)
 }
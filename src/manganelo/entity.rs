@@ -1,25 +1,32 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 use mangaverse_entity::models::{
-    chapter::ChapterTable, genre::Genre, manga::MangaTable, page::PageTable, source::SourceTable,
+    chapter::ChapterTable, manga::MangaTable, page::PageTable, source::SourceTable,
 };
 use scraper::{Html, Selector};
-use sqlx::{
-    types::chrono::{NaiveDateTime, Utc},
-    MySql, Pool,
-};
+use sqlx::{types::chrono::Utc, MySql, Pool};
 
 use lazy_static::lazy_static;
 
-use crate::{db::source::insert_source_if_not_exists, MSError, Result};
+use crate::parse::chapter::{number_text, ChapterNumber};
+use crate::parse::date::{DateFormat, RelativeDates, ENGLISH};
+use crate::parse::text::normalize_manga_text;
+use crate::{db::genre::GenreMap, db::source::insert_source_if_not_exists, MSError, Result};
 
 const AUTHOR: &str = "Author(s) :";
 const ALTERNATIVE_NAME: &str = "Alternative :";
 const STATUS: &str = "Status :";
 const GENRES: &str = "Genres :";
 const UPDATED: &str = "Updated :";
+const DESCRIPTION_PREFIX: &str = "Description :";
 const SOURCE_NAME: &str = "manganelo";
 
+const DATES: DateFormat = DateFormat {
+    locale: &ENGLISH,
+    utc_offset: 0,
+    formats: &["%b %d,%Y - %H:%M", "%b %d,%Y %H:%M"],
+};
+
 lazy_static! {
     static ref GENRE_SELECTOR: Selector =
         Selector::parse("div.advanced-search-tool-genres-list > span").unwrap();
@@ -37,37 +44,64 @@ lazy_static! {
         Selector::parse("div.container-chapter-reader > img").unwrap();
 }
 
-pub async fn get_manganelo_genres() -> Result<HashSet<String>> {
+/// Genre names offered by the site along with the page listing each genre
+pub async fn get_manganelo_genres() -> Result<HashMap<String, Option<String>>> {
     let url = "https://manganato.com/genre-all";
 
     let response_text = reqwest::get(url).await?.text().await?;
 
-    let doc = Html::parse_document(&response_text);
+    Ok(parse_genres(&Html::parse_document(&response_text)))
+}
 
-    Ok(doc
-        .select(&GENRE_SELECTOR)
-        .map(|f| f.text().collect::<String>().trim().to_lowercase())
-        .collect())
+pub fn parse_genres(doc: &Html) -> HashMap<String, Option<String>> {
+    doc.select(&GENRE_SELECTOR)
+        .map(|f| {
+            (
+                f.text().collect::<String>().trim().to_lowercase(),
+                f.value()
+                    .attr("data-i")
+                    .map(|i| format!("https://manganato.com/genre-{}", i)),
+            )
+        })
+        .collect()
 }
 
 pub async fn get_manganelo_source(pool: &Pool<MySql>) -> Result<SourceTable> {
     insert_source_if_not_exists(SOURCE_NAME, 2, pool).await
 }
 
-#[allow(unused_must_use)]
 pub async fn get_manga<'a>(
     url: String,
     sc: &'a SourceTable,
-    map: &'a HashMap<String, Genre>,
-) -> Result<MangaTable<'a>> {
+    map: &'a GenreMap,
+) -> Result<(MangaTable<'a>, RelativeDates)> {
+    let text = reqwest::get(url.as_str()).await?.text().await?;
+
+    let (mut mng, relative) = parse_manga(&Html::parse_document(text.as_str()), url, sc, map)?;
+
+    for yt in mng.chapters.iter_mut() {
+        if let Ok(pages) = populate_chapter(yt.chapter_id.as_str()).await {
+            yt.pages = pages;
+        }
+    }
+
+    Ok((mng, relative))
+}
+
+/// Reads a manga page into everything but the chapters' pages, along with which of its dates
+/// were relative
+pub fn parse_manga<'a>(
+    doc: &Html,
+    url: String,
+    sc: &'a SourceTable,
+    map: &'a GenreMap,
+) -> Result<(MangaTable<'a>, RelativeDates)> {
     let mut mng: MangaTable = MangaTable::new(sc);
+    let mut relative = RelativeDates::default();
     mng.is_listed = true;
     mng.url = url;
 
     {
-        let doc =
-            Html::parse_document(reqwest::get(mng.url.as_str()).await?.text().await?.as_str());
-
         mng.name.extend(
             doc.select(&NAME_SELECTOR)
                 .next()
@@ -138,16 +172,24 @@ pub async fn get_manga<'a>(
 
         for (label, value) in metadata_table {
             if label.text().collect::<String>() == UPDATED {
+                //the hour is already 24h, the trailing AM/PM only gets in the way
                 let y = value.text().collect::<String>();
-                let x = y[0..y.len() - 3].trim();
-                mng.last_updated = NaiveDateTime::parse_from_str(x, "%b %d,%Y - %H:%M").ok();
+                let x = y.trim().trim_end_matches(" AM").trim_end_matches(" PM");
+                match DATES.parse(x) {
+                    Ok(d) => {
+                        mng.last_updated = Some(d.naive_utc());
+                        relative.last_updated = d.is_relative();
+                    }
+                    Err(e) => println!("{}", e.message),
+                }
             }
         }
 
         if let Some(x) = doc.select(&DESCRIPTION_SELECTOR).next() {
-            let mut u = x.text().collect::<String>();
-            u.drain(0..=13);
-            mng.description.push_str(u.as_str().trim());
+            let u = x.text().collect::<String>();
+            let u = u.trim_start();
+            mng.description
+                .push_str(u.strip_prefix(DESCRIPTION_PREFIX).unwrap_or(u));
         }
 
         let iter_label = doc.select(&CHAPTER_LABEL_SELECTOR);
@@ -159,55 +201,49 @@ pub async fn get_manga<'a>(
             let mut t = ChapterTable {
                 sequence_number: idx as i32,
                 last_watch_time: Utc::now().timestamp_millis(),
-                updated_at: NaiveDateTime::parse_from_str(
-                    t2.value().attr("title").unwrap_or("").trim(),
-                    "%b %d,%Y %H:%M",
-                )
-                .ok(),
                 ..Default::default()
             };
 
             let t1_text = t1.text().collect::<String>();
-            let y = t1_text.find(':');
-            let chp = t1_text.find("Chapter");
-            t.chapter_name = t1_text.clone();
-            if chp.is_none() {
-                t.chapter_name = t1_text;
-            } else if y.is_some() {
-                let act_y = y.unwrap();
-                let act_chp = chp.unwrap();
-                if act_y + 2 < t1_text.len() {
-                    t.chapter_name = t1_text[act_y + 2..].trim().to_string();
-                }
-                if act_chp + "Chapter".len() + 1 < act_y && act_y < t1_text.len() {
-                    t.chapter_number = t1_text[act_chp + "Chapter".len() + 1..act_y]
-                        .trim()
-                        .to_string();
-                }
-            } else {
-                let act_chp = chp.unwrap();
-                if act_chp + "Chapter".len() + 1 < t1_text.len() {
-                    t.chapter_number = t1_text[act_chp + "Chapter".len() + 1..].trim().to_string()
-                }
-            }
+            t.chapter_name = match t1_text.split_once(':') {
+                Some((_, name)) if !name.trim().is_empty() => name.trim().to_string(),
+                _ => t1_text.trim().to_string(),
+            };
 
             if let Some(url_chp) = t1.value().attr("href") {
                 t.chapter_id = url_chp.to_string();
             }
 
+            //the title holds the full date, the text may only say how long ago it was
+            let updated_at = match t2.value().attr("title").map(|f| DATES.parse(f)) {
+                Some(Ok(d)) => Ok(d),
+                _ => DATES.parse(t2.text().collect::<String>().as_str()),
+            };
+
+            match updated_at {
+                Ok(d) => {
+                    t.updated_at = Some(d.naive_utc());
+                    if d.is_relative() {
+                        relative.chapters.insert(t.chapter_id.clone());
+                    }
+                }
+                Err(e) => println!("{}", e.message),
+            }
+
+            //the site's wording is kept for display, the url only gives the canonical number
+            t.chapter_number = if ChapterNumber::parse(t1_text.as_str()).is_some() {
+                number_text(t1_text.as_str())
+            } else {
+                ChapterNumber::from_url(t.chapter_id.as_str())
+                    .map(|f| f.to_string())
+                    .unwrap_or_default()
+            };
+
             mng.chapters.push(t);
         }
     }
 
     {
-        for yt in mng.chapters.iter_mut() {
-            let res = populate_chapter(yt.chapter_id.as_str()).await;
-
-            if res.is_ok() {
-                yt.pages = res.unwrap();
-            }
-        }
-
         mng.chapters.reverse();
 
         let sz = mng.chapters.len() as i32;
@@ -217,21 +253,26 @@ pub async fn get_manga<'a>(
         }
     }
 
-    Ok(mng)
+    normalize_manga_text(&mut mng);
+
+    Ok((mng, relative))
 }
 
 async fn populate_chapter(url_chp: &str) -> Result<Vec<PageTable>> {
-    Ok(
-        Html::parse_document(reqwest::get(url_chp).await?.text().await?.as_str())
-            .select(&IMAGES_SELECTOR)
-            .filter_map(|f| f.value().attr("src"))
-            .map(ToString::to_string)
-            .enumerate()
-            .map(|(idx, u)| PageTable {
-                url: u,
-                page_number: idx as i32,
-                ..Default::default()
-            })
-            .collect(),
-    )
+    let text = reqwest::get(url_chp).await?.text().await?;
+
+    Ok(parse_pages(&Html::parse_document(text.as_str())))
+}
+
+pub fn parse_pages(doc: &Html) -> Vec<PageTable> {
+    doc.select(&IMAGES_SELECTOR)
+        .filter_map(|f| f.value().attr("src"))
+        .map(ToString::to_string)
+        .enumerate()
+        .map(|(idx, u)| PageTable {
+            url: u,
+            page_number: idx as i32,
+            ..Default::default()
+        })
+        .collect()
 }
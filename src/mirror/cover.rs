@@ -0,0 +1,243 @@
+use std::io::Cursor;
+use std::sync::Arc;
+
+use image::{imageops::FilterType, DynamicImage, ImageOutputFormat};
+use sqlx::{MySql, Pool};
+
+use crate::db::cover::{
+    get_uncached_covers, set_listing_cover, set_listing_cover_failed, CachedCover, CoverRetry,
+};
+use crate::{MSError, Result};
+
+use super::{content_hash, content_key, fetch_image, http_client, sniff_content_type, Storage};
+
+/// Thumbnail widths, heights follow the cover's aspect ratio
+pub const SMALL_WIDTH: u32 = 120;
+pub const MEDIUM_WIDTH: u32 = 240;
+pub const LARGE_WIDTH: u32 = 480;
+
+#[derive(Debug, Default, Clone)]
+pub struct CoverReport {
+    pub cached: usize,
+    pub failed: usize,
+}
+
+/// Copies covers into a storage and makes thumbnails of them for the listing.
+///
+/// Files are keyed by the cover's hash, so a cover shared by several manga or re-uploaded under a
+/// new url is only stored once. New covers are queued by `insert_manga` and `update_manga`, `run`
+/// only retries the ones that failed or whose refresh never finished.
+pub struct CoverCache {
+    pub storage: Box<dyn Storage>,
+    /// Covers looked at per `run`
+    pub batch: u32,
+    /// Covers larger than this many bytes are refused
+    pub max_bytes: usize,
+    /// JPEG quality of the thumbnails
+    pub quality: u8,
+    /// Retries of covers that failed to download or store
+    pub retry: CoverRetry,
+    client: reqwest::Client,
+    pool: Pool<MySql>,
+}
+
+impl CoverCache {
+    pub fn new(storage: Box<dyn Storage>, pool: Pool<MySql>) -> Result<Self> {
+        Ok(Self {
+            storage,
+            batch: 200,
+            max_bytes: 10 * 1024 * 1024,
+            quality: 85,
+            retry: CoverRetry::default(),
+            client: http_client()?,
+            pool,
+        })
+    }
+
+    /// Caches a newly stored cover on a spawned task, so the scrape storing it doesn't wait on the
+    /// download. Must be called within a tokio runtime.
+    pub fn queue(self: &Arc<Self>, manga_id: &str, cover_url: &str, referer: &str) {
+        let this = Arc::clone(self);
+        let (manga_id, cover_url, referer) = (
+            manga_id.to_string(),
+            cover_url.to_string(),
+            referer.to_string(),
+        );
+
+        tokio::spawn(async move {
+            if let Err(e) = this
+                .refresh(manga_id.as_str(), cover_url.as_str(), referer.as_str())
+                .await
+            {
+                println!("Failed to record cover {}: {}", cover_url, e.message);
+            }
+        });
+    }
+
+    /// Downloads a cover and stores it with its thumbnails
+    pub async fn fetch(&self, cover_url: &str, referer: &str) -> Result<CachedCover> {
+        let bytes = fetch_image(&self.client, cover_url, Some(referer), self.max_bytes).await?;
+
+        let content_type = sniff_content_type(&bytes).ok_or_else(|| MSError {
+            message: format!("{} is not an image", cover_url),
+            err_type: crate::MSErrorType::OtherError,
+        })?;
+
+        let hash = content_hash(&bytes);
+
+        let key = format!("covers/{}", content_key(hash.as_str(), content_type));
+        if !self.storage.exists(key.as_str()).await? {
+            self.storage.put(key.as_str(), &bytes, content_type).await?;
+        }
+
+        let mut out = CachedCover {
+            original: self.storage.url(key.as_str()),
+            ..Default::default()
+        };
+
+        let mut missing = Vec::new();
+
+        for width in [SMALL_WIDTH, MEDIUM_WIDTH, LARGE_WIDTH] {
+            if !self
+                .storage
+                .exists(thumb_key(hash.as_str(), width).as_str())
+                .await?
+            {
+                missing.push(width);
+            }
+        }
+
+        if !missing.is_empty() {
+            let quality = self.quality;
+            let url = cover_url.to_string();
+
+            let thumbs = match tokio::runtime::Handle::try_current() {
+                //decoding and scaling a large cover takes long enough to stall other tasks
+                Ok(rt) => rt
+                    .spawn_blocking(move || thumbnails(url.as_str(), &bytes, &missing, quality))
+                    .await
+                    .map_err(|e| MSError {
+                        message: format!("Failed to make thumbnails of {}: {}", cover_url, e),
+                        err_type: crate::MSErrorType::JoinHandleError,
+                    })??,
+                //outside a tokio runtime there are no other tasks to stall
+                Err(_) => thumbnails(url.as_str(), &bytes, &missing, quality)?,
+            };
+
+            for (width, t) in thumbs {
+                self.storage
+                    .put(thumb_key(hash.as_str(), width).as_str(), &t, "image/jpeg")
+                    .await?;
+            }
+        }
+
+        for (width, slot) in [
+            (SMALL_WIDTH, &mut out.small),
+            (MEDIUM_WIDTH, &mut out.medium),
+            (LARGE_WIDTH, &mut out.large),
+        ] {
+            *slot = self.storage.url(thumb_key(hash.as_str(), width).as_str());
+        }
+
+        Ok(out)
+    }
+
+    /// Caches the cover of a listed manga and records the result on its listing. A cover that
+    /// can't be cached is logged and recorded, the listing keeps using `cover_url`. Failures to
+    /// download or store it are retried by later runs, a cover that is not a usable image is not.
+    /// A connection is only taken from the pool once the cover is fetched.
+    pub async fn refresh(&self, manga_id: &str, cover_url: &str, referer: &str) -> Result<bool> {
+        let fetched = self.fetch(cover_url, referer).await;
+        let mut conn = self.pool.acquire().await?;
+
+        match fetched {
+            Ok(c) => {
+                set_listing_cover(manga_id, cover_url, &c, &mut conn).await?;
+                Ok(true)
+            }
+            Err(e) => {
+                println!("Failed to cache cover {}: {}", cover_url, e.message);
+                let transient = matches!(
+                    e.err_type,
+                    crate::MSErrorType::NetworkError | crate::MSErrorType::IOError
+                );
+                set_listing_cover_failed(
+                    manga_id,
+                    cover_url,
+                    e.message.as_str(),
+                    Some(&self.retry).filter(|_| transient),
+                    &mut conn,
+                )
+                .await?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Retries covers of listed manga that are due, see `get_uncached_covers`
+    pub async fn run(&self) -> Result<CoverReport> {
+        let mut report = CoverReport::default();
+
+        let pending = get_uncached_covers(self.batch, &mut self.pool.acquire().await?).await?;
+
+        for c in pending {
+            if self
+                .refresh(c.manga_id.as_str(), c.cover_url.as_str(), c.url.as_str())
+                .await?
+            {
+                report.cached += 1;
+            } else {
+                report.failed += 1;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Decodes a cover and makes a JPEG thumbnail of it for each of `widths`
+fn thumbnails(
+    cover_url: &str,
+    bytes: &[u8],
+    widths: &[u32],
+    quality: u8,
+) -> Result<Vec<(u32, Vec<u8>)>> {
+    let img = image::load_from_memory(bytes).map_err(|e| MSError {
+        message: format!("Failed to decode {}: {}", cover_url, e),
+        err_type: crate::MSErrorType::OtherError,
+    })?;
+
+    widths
+        .iter()
+        .map(|w| Ok((*w, resize(&img, *w, quality)?)))
+        .collect()
+}
+
+/// Scales down to `width`, never up, and encodes as JPEG
+fn resize(img: &DynamicImage, width: u32, quality: u8) -> Result<Vec<u8>> {
+    let scaled = if img.width() > width {
+        img.resize(width, u32::MAX, FilterType::Triangle)
+    } else {
+        img.clone()
+    };
+
+    let mut buf = Vec::new();
+    DynamicImage::ImageRgb8(scaled.to_rgb8())
+        .write_to(&mut Cursor::new(&mut buf), ImageOutputFormat::Jpeg(quality))
+        .map_err(|e| MSError {
+            message: e.to_string(),
+            err_type: crate::MSErrorType::OtherError,
+        })?;
+
+    Ok(buf)
+}
+
+fn thumb_key(hash: &str, width: u32) -> String {
+    format!(
+        "covers/{}/{}/{}_{}.jpg",
+        &hash[..2],
+        &hash[2..4],
+        hash,
+        width
+    )
+}
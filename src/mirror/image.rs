@@ -0,0 +1,207 @@
+fn be16(b: &[u8], at: usize) -> Option<u32> {
+    let s = b.get(at..at + 2)?;
+    Some((u32::from(s[0]) << 8) | u32::from(s[1]))
+}
+
+fn le16(b: &[u8], at: usize) -> Option<u32> {
+    let s = b.get(at..at + 2)?;
+    Some((u32::from(s[1]) << 8) | u32::from(s[0]))
+}
+
+fn le24(b: &[u8], at: usize) -> Option<u32> {
+    let s = b.get(at..at + 3)?;
+    Some((u32::from(s[2]) << 16) | (u32::from(s[1]) << 8) | u32::from(s[0]))
+}
+
+fn be32(b: &[u8], at: usize) -> Option<u32> {
+    let s = b.get(at..at + 4)?;
+    Some(u32::from_be_bytes([s[0], s[1], s[2], s[3]]))
+}
+
+/// Walks the segments up to the first start-of-frame, which holds the size
+fn jpeg_dimensions(b: &[u8]) -> Option<(u32, u32)> {
+    let mut i = 2;
+
+    loop {
+        //markers may be padded with any number of 0xFF
+        while *b.get(i)? == 0xFF && *b.get(i + 1)? == 0xFF {
+            i += 1;
+        }
+        if *b.get(i)? != 0xFF {
+            return None;
+        }
+
+        let marker = *b.get(i + 1)?;
+        let len = be16(b, i + 2)? as usize;
+
+        //SOF0 to SOF15, except DHT, JPG and DAC which share the range
+        if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+            return Some((be16(b, i + 7)?, be16(b, i + 5)?));
+        }
+
+        i += 2 + len;
+    }
+}
+
+fn webp_dimensions(b: &[u8]) -> Option<(u32, u32)> {
+    match b.get(12..16)? {
+        b"VP8 " => Some((le16(b, 26)? & 0x3FFF, le16(b, 28)? & 0x3FFF)),
+        b"VP8L" => {
+            let s = b.get(21..25)?;
+            let w = 1 + (u32::from(s[0]) | ((u32::from(s[1]) & 0x3F) << 8));
+            let h = 1
+                + ((u32::from(s[1]) >> 6)
+                    | (u32::from(s[2]) << 2)
+                    | ((u32::from(s[3]) & 0x0F) << 10));
+            Some((w, h))
+        }
+        b"VP8X" => Some((1 + le24(b, 24)?, 1 + le24(b, 27)?)),
+        _ => None,
+    }
+}
+
+/// Width and height read from the header of an image of the given type, without decoding it
+pub fn dimensions(bytes: &[u8], content_type: &str) -> Option<(u32, u32)> {
+    match content_type {
+        "image/png" => Some((be32(bytes, 16)?, be32(bytes, 20)?)),
+        "image/gif" => Some((le16(bytes, 6)?, le16(bytes, 8)?)),
+        "image/jpeg" => jpeg_dimensions(bytes),
+        "image/webp" => webp_dimensions(bytes),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(w: u32, h: u32) -> Vec<u8> {
+        let mut b = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        b.extend(w.to_be_bytes());
+        b.extend(h.to_be_bytes());
+        b.extend([8, 6, 0, 0, 0]);
+        b
+    }
+
+    fn gif(w: u16, h: u16) -> Vec<u8> {
+        let mut b = b"GIF89a".to_vec();
+        b.extend(w.to_le_bytes());
+        b.extend(h.to_le_bytes());
+        b
+    }
+
+    /// SOI, an APP0 and a DHT segment to skip, fill bytes, then a baseline start-of-frame
+    fn jpeg(w: u16, h: u16) -> Vec<u8> {
+        let mut b = vec![0xFF, 0xD8];
+        b.extend([0xFF, 0xE0, 0x00, 0x10]);
+        b.extend(b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+        b.extend([0xFF, 0xC4, 0x00, 0x03, 0x00]);
+        b.extend([0xFF, 0xFF, 0xFF, 0xC0, 0x00, 0x11, 0x08]);
+        b.extend(h.to_be_bytes());
+        b.extend(w.to_be_bytes());
+        b.extend([0x03, 0x01, 0x22, 0x00]);
+        b
+    }
+
+    fn riff(chunk: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut b = b"RIFF\0\0\0\0WEBP".to_vec();
+        b.extend(chunk);
+        b.extend((payload.len() as u32).to_le_bytes());
+        b.extend(payload);
+        b
+    }
+
+    fn webp_lossy(w: u16, h: u16) -> Vec<u8> {
+        let mut p = vec![0x30, 0x01, 0x00, 0x9D, 0x01, 0x2A];
+        p.extend(w.to_le_bytes());
+        p.extend(h.to_le_bytes());
+        riff(b"VP8 ", &p)
+    }
+
+    fn webp_lossless(w: u32, h: u32) -> Vec<u8> {
+        let bits = (w - 1) | ((h - 1) << 14);
+        let mut p = vec![0x2F];
+        p.extend(bits.to_le_bytes());
+        riff(b"VP8L", &p)
+    }
+
+    fn webp_extended(w: u32, h: u32) -> Vec<u8> {
+        let mut p = vec![0; 4];
+        p.extend(&(w - 1).to_le_bytes()[..3]);
+        p.extend(&(h - 1).to_le_bytes()[..3]);
+        riff(b"VP8X", &p)
+    }
+
+    /// Type, bytes, size, and how many leading bytes hold the size
+    type Fixture = (&'static str, Vec<u8>, (u32, u32), usize);
+
+    fn fixtures() -> Vec<Fixture> {
+        vec![
+            ("image/png", png(800, 1200), (800, 1200), 24),
+            ("image/gif", gif(320, 240), (320, 240), 10),
+            ("image/jpeg", jpeg(720, 10_000), (720, 10_000), 36),
+            ("image/webp", webp_lossy(690, 980), (690, 980), 30),
+            ("image/webp", webp_lossless(16_384, 3), (16_384, 3), 25),
+            ("image/webp", webp_extended(5000, 9000), (5000, 9000), 30),
+        ]
+    }
+
+    #[test]
+    fn reads_headers() {
+        for (content_type, bytes, size, _) in fixtures() {
+            assert_eq!(
+                dimensions(&bytes, content_type),
+                Some(size),
+                "{}",
+                content_type
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_headers_give_none() {
+        for (content_type, bytes, size, header) in fixtures() {
+            for n in 0..header {
+                assert_eq!(
+                    dimensions(&bytes[..n], content_type),
+                    None,
+                    "{} cut at {}",
+                    content_type,
+                    n
+                );
+            }
+            assert_eq!(dimensions(&bytes[..header], content_type), Some(size));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_types_and_chunks() {
+        assert_eq!(dimensions(&png(1, 1), "image/avif"), None);
+        assert_eq!(dimensions(&riff(b"ALPH", &[0; 16]), "image/webp"), None);
+        assert_eq!(dimensions(&[0xFF, 0xD8, 0x00, 0x00], "image/jpeg"), None);
+    }
+
+    #[test]
+    fn survives_garbage() {
+        let mut state: u32 = 0x2545_F491;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        };
+
+        for len in 0..512 {
+            let mut b: Vec<u8> = (0..len).map(|_| next()).collect();
+            for content_type in ["image/png", "image/gif", "image/jpeg", "image/webp"] {
+                dimensions(&b, content_type);
+            }
+            //a valid start so the segment walk is exercised too
+            if b.len() > 4 {
+                b[..2].copy_from_slice(&[0xFF, 0xD8]);
+                b[2] = 0xFF;
+                dimensions(&b, "image/jpeg");
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use sqlx::{MySql, Pool};
+
+use crate::db::mirror::{
+    get_uninspected_pages, set_page_inspect_failed, set_page_metadata, PageMetadata,
+};
+use crate::Result;
+
+use super::{content_hash, fetch_page, http_client, image::dimensions, sniff_content_type};
+
+/// Everything recorded about a page image that can be read from its bytes
+pub fn page_metadata(bytes: &[u8]) -> PageMetadata {
+    let mime_type = sniff_content_type(bytes);
+    let (width, height) = match mime_type.and_then(|f| dimensions(bytes, f)) {
+        Some((w, h)) => (Some(w), Some(h)),
+        None => (None, None),
+    };
+
+    PageMetadata {
+        width,
+        height,
+        bytes: bytes.len() as u64,
+        mime_type: mime_type.map(ToString::to_string),
+        content_hash: content_hash(bytes),
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct InspectReport {
+    pub inspected: usize,
+    pub failed: usize,
+}
+
+/// Fetches pages that were never inspected and records what their images are. Optional, the
+/// mirror fills in the same columns for every page it copies.
+pub struct Inspector {
+    /// Pages looked at per run
+    pub batch: u32,
+    /// Images larger than this many bytes are recorded as failures
+    pub max_bytes: usize,
+    client: reqwest::Client,
+}
+
+impl Inspector {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            batch: 500,
+            max_bytes: 20 * 1024 * 1024,
+            client: http_client()?,
+        })
+    }
+
+    /// Connections are only taken from the pool to read and write rows, never held across a
+    /// download.
+    pub async fn run(&self, pool: &Pool<MySql>) -> Result<InspectReport> {
+        let mut report = InspectReport::default();
+
+        let pending = get_uninspected_pages(self.batch, &mut pool.acquire().await?).await?;
+
+        for p in pending {
+            match fetch_page(&self.client, &p, self.max_bytes).await {
+                Ok(bytes) => {
+                    set_page_metadata(&p.page, &page_metadata(&bytes), &mut pool.acquire().await?)
+                        .await?;
+                    report.inspected += 1;
+                }
+                Err(e) => {
+                    println!("Failed to inspect {}: {}", p.page.url, e.message);
+                    set_page_inspect_failed(
+                        &p.page,
+                        e.message.as_str(),
+                        &mut pool.acquire().await?,
+                    )
+                    .await?;
+                    report.failed += 1;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,48 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::Result;
+
+use super::Storage;
+
+/// Stores images under a directory that a web server exposes at `public_base`
+pub struct LocalStorage {
+    pub root: PathBuf,
+    pub public_base: String,
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        match tokio::fs::metadata(self.root.join(key)).await {
+            Ok(m) => Ok(m.is_file()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn put(&self, key: &str, bytes: &[u8], _content_type: &str) -> Result<()> {
+        let path = self.root.join(key);
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+
+        //write under a temp name and rename so readers never see half an image
+        let tmp = path.with_file_name(format!(".{}.tmp", Uuid::new_v4()));
+        tokio::fs::write(&tmp, bytes).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+
+        Ok(())
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("{}/{}", self.public_base.trim_end_matches('/'), key)
+    }
+}
@@ -0,0 +1,253 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::header::REFERER;
+use sha2::{Digest, Sha256};
+use sqlx::{MySql, Pool};
+
+use crate::db::mirror::{
+    get_blob, get_unmirrored_pages, insert_blob, set_page_metadata, set_page_mirror_failed,
+    set_page_mirrored, PageBlob, PendingPage,
+};
+use crate::{MSError, Result};
+
+pub mod cover;
+pub mod image;
+pub mod inspect;
+pub mod local;
+pub mod s3;
+
+use inspect::page_metadata;
+
+/// Image hosts that accept a connection and then stall would otherwise hold a run up forever
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Client shared by everything here that downloads or uploads images
+pub(crate) fn http_client() -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()?)
+}
+
+/// Somewhere page images can be copied to and served from
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Short name recorded with each blob, such as "local" or "s3"
+    fn name(&self) -> &str;
+
+    async fn exists(&self, key: &str) -> Result<bool>;
+
+    async fn put(&self, key: &str, bytes: &[u8], content_type: &str) -> Result<()>;
+
+    /// Public url readers fetch the stored object from
+    fn url(&self, key: &str) -> String;
+}
+
+/// Works out an image's type from its first bytes. Image hosts often answer with an html error
+/// page or the wrong header, so the bytes are trusted over `Content-Type`.
+pub fn sniff_content_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" && &bytes[8..12] == b"avif" {
+        Some("image/avif")
+    } else {
+        None
+    }
+}
+
+fn extension(content_type: &str) -> &'static str {
+    match content_type {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/avif" => "avif",
+        _ => "bin",
+    }
+}
+
+/// Hex sha256 of the bytes
+pub fn content_hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Storage key of an image, spread over two directory levels so no directory grows too large
+pub fn content_key(hash: &str, content_type: &str) -> String {
+    format!(
+        "{}/{}/{}.{}",
+        &hash[..2],
+        &hash[2..4],
+        hash,
+        extension(content_type)
+    )
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct MirrorReport {
+    /// Pages whose image was uploaded
+    pub stored: usize,
+    /// Pages whose image was already in storage
+    pub reused: usize,
+    pub failed: usize,
+}
+
+/// Copies page images into a storage, one batch of unmirrored pages per `run`.
+pub struct Mirror {
+    pub storage: Box<dyn Storage>,
+    /// Pages looked at per run
+    pub batch: u32,
+    /// Pages failing this many times are left alone
+    pub max_attempts: i32,
+    /// Images larger than this many bytes are refused
+    pub max_bytes: usize,
+    client: reqwest::Client,
+}
+
+impl Mirror {
+    pub fn new(storage: Box<dyn Storage>) -> Result<Self> {
+        Ok(Self {
+            storage,
+            batch: 500,
+            max_attempts: 5,
+            max_bytes: 20 * 1024 * 1024,
+            client: http_client()?,
+        })
+    }
+
+    /// Connections are only taken from the pool to read and write rows, never held across a
+    /// download or upload.
+    pub async fn run(&self, pool: &Pool<MySql>) -> Result<MirrorReport> {
+        let mut report = MirrorReport::default();
+
+        let pending = get_unmirrored_pages(
+            self.batch,
+            self.max_attempts,
+            self.storage.name(),
+            &mut pool.acquire().await?,
+        )
+        .await?;
+
+        for p in pending {
+            match self.mirror_page(&p, pool).await {
+                Ok(true) => report.stored += 1,
+                Ok(false) => report.reused += 1,
+                Err(e) => {
+                    println!("Failed to mirror {}: {}", p.page.url, e.message);
+                    set_page_mirror_failed(&p.page, e.message.as_str(), &mut pool.acquire().await?)
+                        .await?;
+                    report.failed += 1;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Downloads one page and stores it unless the same bytes are stored already. Returns whether
+    /// anything was uploaded.
+    async fn mirror_page(&self, p: &PendingPage, pool: &Pool<MySql>) -> Result<bool> {
+        let bytes = fetch_page(&self.client, p, self.max_bytes).await?;
+
+        //the bytes are in hand anyway, so the page needs no separate inspection
+        let meta = page_metadata(&bytes);
+        let hash = meta.content_hash.clone();
+
+        let known = {
+            let mut conn = pool.acquire().await?;
+            set_page_metadata(&p.page, &meta, &mut conn).await?;
+            get_blob(hash.as_str(), self.storage.name(), &mut conn)
+                .await?
+                .is_some()
+        };
+
+        let content_type = sniff_content_type(&bytes).ok_or_else(|| MSError {
+            message: format!("{} is not an image", p.page.url),
+            err_type: crate::MSErrorType::OtherError,
+        })?;
+
+        let mut stored = false;
+        let mut blob = None;
+
+        if !known {
+            let key = content_key(hash.as_str(), content_type);
+
+            if !self.storage.exists(key.as_str()).await? {
+                self.storage.put(key.as_str(), &bytes, content_type).await?;
+                stored = true;
+            }
+
+            blob = Some(PageBlob {
+                hash,
+                storage: self.storage.name().to_string(),
+                url: self.storage.url(key.as_str()),
+                storage_key: key,
+                content_type: content_type.to_string(),
+            });
+        }
+
+        let mut conn = pool.acquire().await?;
+        if let Some(b) = blob {
+            insert_blob(&b, &mut conn).await?;
+        }
+        set_page_mirrored(&p.page, &mut conn).await?;
+
+        Ok(stored)
+    }
+}
+
+/// Downloads an image, refusing anything over `max_bytes`
+pub(crate) async fn fetch_image(
+    client: &reqwest::Client,
+    url: &str,
+    referer: Option<&str>,
+    max_bytes: usize,
+) -> Result<Vec<u8>> {
+    let mut req = client.get(url);
+    if let Some(r) = referer {
+        req = req.header(REFERER, r);
+    }
+
+    let mut resp = req.send().await?.error_for_status()?;
+
+    if resp
+        .content_length()
+        .is_some_and(|f| f as usize > max_bytes)
+    {
+        return Err(too_large(url, max_bytes));
+    }
+
+    //read in chunks, the length header may be missing or wrong
+    let mut bytes = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        if bytes.len() + chunk.len() > max_bytes {
+            return Err(too_large(url, max_bytes));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(bytes)
+}
+
+fn too_large(url: &str, max_bytes: usize) -> MSError {
+    MSError {
+        message: format!("{} is larger than {} bytes", url, max_bytes),
+        err_type: crate::MSErrorType::OtherError,
+    }
+}
+
+/// Downloads a page image, with the chapter it belongs to as the referer
+pub(crate) async fn fetch_page(
+    client: &reqwest::Client,
+    p: &PendingPage,
+    max_bytes: usize,
+) -> Result<Vec<u8>> {
+    fetch_image(client, p.page.url.as_str(), p.referer.as_deref(), max_bytes).await
+}
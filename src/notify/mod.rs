@@ -0,0 +1,134 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use mangaverse_entity::models::manga::MangaTable;
+use serde::Serialize;
+
+use crate::db::diff::{ChapterSummary, MangaDiff};
+use crate::Result;
+
+pub mod spool;
+pub mod webhook;
+
+/// Fired when a refresh stores chapters that were not seen before.
+#[derive(Debug, Clone, Serialize)]
+pub struct NewChapterEvent {
+    pub manga_id: String,
+    pub linked_id: String,
+    pub manga_name: String,
+    pub manga_url: String,
+    pub source: String,
+    pub chapters: Vec<ChapterSummary>,
+}
+
+impl NewChapterEvent {
+    pub fn new(mng: &MangaTable<'_>, chapters: Vec<ChapterSummary>) -> Self {
+        Self {
+            manga_id: mng.id.clone(),
+            linked_id: mng.linked_id.clone(),
+            manga_name: mng.name.clone(),
+            manga_url: mng.url.clone(),
+            source: mng.source.name.clone(),
+            chapters,
+        }
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, event: &NewChapterEvent) -> Result<()>;
+}
+
+/// Which manga a subscriber wants to hear about. Both sets empty means everything.
+#[derive(Debug, Clone, Default)]
+pub struct SubscriberFilter {
+    pub manga_ids: HashSet<String>,
+    pub linked_ids: HashSet<String>,
+}
+
+impl SubscriberFilter {
+    pub fn matches(&self, event: &NewChapterEvent) -> bool {
+        (self.manga_ids.is_empty() && self.linked_ids.is_empty())
+            || self.manga_ids.contains(&event.manga_id)
+            || self.linked_ids.contains(&event.linked_id)
+    }
+}
+
+pub struct Subscriber {
+    pub name: String,
+    pub filter: SubscriberFilter,
+    pub notifier: Box<dyn Notifier>,
+}
+
+/// Fans new-chapter events out to every interested subscriber, retrying failed deliveries.
+pub struct Notifications {
+    pub subscribers: Vec<Subscriber>,
+    pub retries: u32,
+    /// Delay before the first retry, doubled for every retry after it
+    pub backoff: Duration,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            subscribers: Vec::default(),
+            retries: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Notifications {
+    pub fn subscribe(
+        &mut self,
+        name: &str,
+        filter: SubscriberFilter,
+        notifier: Box<dyn Notifier>,
+    ) -> &mut Self {
+        self.subscribers.push(Subscriber {
+            name: name.to_string(),
+            filter,
+            notifier,
+        });
+        self
+    }
+
+    /// Announces the chapters a written `update_manga` run added, if any. Deliveries run on a
+    /// spawned task since retries can take a while, so this must be called within a tokio runtime.
+    pub fn announce(self: &Arc<Self>, stored: &MangaTable<'_>, diff: &MangaDiff) {
+        if diff.chapters_added.is_empty() || diff.quarantined.is_some() || diff.skipped {
+            return;
+        }
+
+        let event = NewChapterEvent::new(stored, diff.chapters_added.clone());
+        let this = Arc::clone(self);
+
+        tokio::spawn(async move { this.dispatch(&event).await });
+    }
+
+    /// Delivers the event to every matching subscriber. A subscriber that still fails after all
+    /// retries is reported but does not stop delivery to the others.
+    pub async fn dispatch(&self, event: &NewChapterEvent) {
+        for s in self.subscribers.iter().filter(|f| f.filter.matches(event)) {
+            let mut delay = self.backoff;
+            let mut attempt = 0;
+            loop {
+                match s.notifier.notify(event).await {
+                    Ok(_) => break,
+                    Err(e) if attempt < self.retries => {
+                        println!("Notifying {} failed, retrying: {}", s.name, e.message);
+                        tokio::time::sleep(delay).await;
+                        delay *= 2;
+                        attempt += 1;
+                    }
+                    Err(e) => {
+                        println!("Giving up on notifying {}: {}", s.name, e.message);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
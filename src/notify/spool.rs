@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::Result;
+
+use super::{NewChapterEvent, Notifier};
+
+/// Writes each event as an email message into a spool directory for a mailer to pick up
+pub struct SpoolNotifier {
+    pub dir: PathBuf,
+    pub from: String,
+    pub to: String,
+}
+
+impl SpoolNotifier {
+    fn render(&self, event: &NewChapterEvent) -> String {
+        let mut body = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {} new chapter(s) of {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n",
+            self.from,
+            self.to,
+            event.chapters.len(),
+            event.manga_name
+        );
+
+        for c in &event.chapters {
+            body.push_str(format!("Chapter {} {}\r\n", c.chapter_number, c.chapter_name).as_str());
+        }
+
+        body.push_str(format!("\r\n{} on {}\r\n", event.manga_url, event.source).as_str());
+
+        body
+    }
+}
+
+#[async_trait]
+impl Notifier for SpoolNotifier {
+    async fn notify(&self, event: &NewChapterEvent) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        //write under a temp name and rename so the mailer never sees half a message
+        let name = Uuid::new_v4().to_string();
+        let tmp = self.dir.join(format!(".{}.tmp", name));
+        tokio::fs::write(&tmp, self.render(event)).await?;
+        tokio::fs::rename(&tmp, self.dir.join(format!("{}.eml", name))).await?;
+
+        Ok(())
+    }
+}
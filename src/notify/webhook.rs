@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::header::CONTENT_TYPE;
+
+use crate::{MSError, Result};
+
+use super::{NewChapterEvent, Notifier};
+
+/// POSTs the event as JSON to a url
+pub struct WebhookNotifier {
+    pub url: String,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            url: url.to_string(),
+            client: reqwest::Client::builder()
+                .connect_timeout(Duration::from_secs(10))
+                .timeout(Duration::from_secs(30))
+                .build()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, event: &NewChapterEvent) -> Result<()> {
+        let body = serde_json::to_string(event).map_err(|e| MSError {
+            message: e.to_string(),
+            err_type: crate::MSErrorType::OtherError,
+        })?;
+
+        self.client
+            .post(self.url.as_str())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+const VOLUME: [&str; 3] = ["vol", "volume", "v"];
+const CHAPTER: [&str; 6] = ["chapter", "chap", "ch", "episode", "ep", "c"];
+const PART: [&str; 2] = ["part", "pt"];
+const EXTRA: [&str; 4] = ["extra", "omake", "bonus", "side"];
+const SPECIAL: [&str; 4] = ["special", "sp", "oneshot", "prologue"];
+
+/// A chapter number as the sites write it, such as "Vol.2 Chapter 10.5 Part 2" or "Extra 3".
+///
+/// Ordering goes by volume, then number, then part, with extras and specials after the regular
+/// chapter sharing their number. Chapters without a volume come after those with one, as sites
+/// only collect older chapters into volumes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ChapterNumber {
+    pub volume: Option<u32>,
+    pub number: Option<u32>,
+    /// Decimal part of the number in thousandths, so 10.5 is 500 and 10.05 is 50
+    pub fraction: u32,
+    pub part: Option<u32>,
+    pub extra: bool,
+    pub special: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Num(String),
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut out = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() {
+            let mut n = String::from(c);
+            while let Some(&d) = chars.peek() {
+                if d.is_ascii_digit() {
+                    n.push(d);
+                    chars.next();
+                } else if d == '.' && !n.contains('.') {
+                    chars.next();
+                    match chars.peek() {
+                        Some(e) if e.is_ascii_digit() => n.push('.'),
+                        _ => break,
+                    }
+                } else {
+                    break;
+                }
+            }
+            out.push(Token::Num(n));
+        } else if c.is_alphabetic() {
+            let mut w: String = c.to_lowercase().collect();
+            while let Some(&d) = chars.peek() {
+                if d.is_alphabetic() {
+                    w.extend(d.to_lowercase());
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            out.push(Token::Word(w));
+        } else if c == '#' {
+            out.push(Token::Word("#".to_string()));
+        }
+    }
+
+    out
+}
+
+/// Splits "10.5" into 10 and 500 thousandths
+fn split_number(n: &str) -> Option<(u32, u32)> {
+    let (whole, frac) = n.split_once('.').unwrap_or((n, ""));
+    let whole = whole.parse().ok()?;
+    let frac = frac
+        .chars()
+        .chain(std::iter::repeat('0'))
+        .take(3)
+        .collect::<String>()
+        .parse()
+        .ok()?;
+    Some((whole, frac))
+}
+
+impl ChapterNumber {
+    /// Reads a chapter number out of a chapter title. Text after a `:` is the chapter's name and
+    /// is ignored unless nothing came before it.
+    pub fn parse(text: &str) -> Option<Self> {
+        let head = match text.split_once(':') {
+            Some((h, _)) if !h.trim().is_empty() => h,
+            _ => text,
+        };
+        Self::from_tokens(tokenize(head))
+    }
+
+    /// Reads a chapter number out of a chapter url, where "chapter-10-5" means 10.5
+    pub fn from_url(url: &str) -> Option<Self> {
+        let last = url
+            .trim_end_matches('/')
+            .rsplit('/')
+            .find(|f| !f.is_empty())?;
+
+        let mut tokens = tokenize(last.replace('_', "-").as_str());
+
+        //two numbers in a row after "chapter" are a whole and decimal part split by the url slug
+        if let Some(i) = tokens
+            .iter()
+            .position(|t| matches!(t, Token::Word(w) if CHAPTER.contains(&w.as_str())))
+        {
+            if let (Some(Token::Num(a)), Some(Token::Num(b))) =
+                (tokens.get(i + 1), tokens.get(i + 2))
+            {
+                if !a.contains('.') && !b.contains('.') {
+                    tokens[i + 1] = Token::Num(format!("{}.{}", a, b));
+                    tokens.remove(i + 2);
+                }
+            }
+        }
+
+        Self::from_tokens(tokens)
+    }
+
+    fn from_tokens(tokens: Vec<Token>) -> Option<Self> {
+        let mut r = ChapterNumber::default();
+        let mut loose = None;
+        let mut iter = tokens.iter().peekable();
+
+        while let Some(t) = iter.next() {
+            let w = match t {
+                Token::Word(w) => w.as_str(),
+                Token::Num(n) => {
+                    if loose.is_none() {
+                        loose = Some(n.as_str());
+                    }
+                    continue;
+                }
+            };
+
+            let next = match iter.peek() {
+                Some(Token::Num(n)) => Some(n.as_str()),
+                _ => None,
+            };
+
+            if VOLUME.contains(&w) && next.is_some() {
+                r.volume = next.and_then(split_number).map(|f| f.0);
+                iter.next();
+            } else if (CHAPTER.contains(&w) || w == "#") && next.is_some() && r.number.is_none() {
+                if let Some((n, f)) = next.and_then(split_number) {
+                    r.number = Some(n);
+                    r.fraction = f;
+                }
+                iter.next();
+            } else if PART.contains(&w) && next.is_some() {
+                r.part = next.and_then(split_number).map(|f| f.0);
+                iter.next();
+            } else if EXTRA.contains(&w) {
+                r.extra = true;
+            } else if SPECIAL.contains(&w) {
+                r.special = true;
+            }
+        }
+
+        if r.number.is_none() {
+            if let Some((n, f)) = loose.and_then(split_number) {
+                r.number = Some(n);
+                r.fraction = f;
+            }
+        }
+
+        if r == ChapterNumber::default() {
+            None
+        } else {
+            Some(r)
+        }
+    }
+
+    fn sort_key(&self) -> (bool, u32, bool, u32, u32, bool, bool, u32) {
+        (
+            self.volume.is_none(),
+            self.volume.unwrap_or_default(),
+            self.number.is_none(),
+            self.number.unwrap_or_default(),
+            self.fraction,
+            self.extra,
+            self.special,
+            self.part.unwrap_or_default(),
+        )
+    }
+}
+
+impl Ord for ChapterNumber {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+impl PartialOrd for ChapterNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The canonical form stored in `number_key`, such as "10.5", "vol 2 10 part 2" or "extra 3".
+/// It parses back to the same `ChapterNumber`.
+impl Display for ChapterNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+
+        if let Some(v) = self.volume {
+            parts.push(format!("vol {}", v));
+        }
+        if self.special {
+            parts.push("special".to_string());
+        }
+        if self.extra {
+            parts.push("extra".to_string());
+        }
+        if let Some(n) = self.number {
+            if self.fraction == 0 {
+                parts.push(n.to_string());
+            } else {
+                let frac = format!("{:03}", self.fraction);
+                parts.push(format!("{}.{}", n, frac.trim_end_matches('0')));
+            }
+        }
+        if let Some(p) = self.part {
+            parts.push(format!("part {}", p));
+        }
+
+        f.write_str(parts.join(" ").as_str())
+    }
+}
+
+/// The number part of a chapter title as the site writes it, for `chapter_number`. The chapter
+/// word is dropped as the number is shown after one, so "Vol.2 Chapter 10: Name" gives "Vol.2 10".
+pub fn number_text(title: &str) -> String {
+    let head = match title.split_once(':') {
+        Some((h, _)) if !h.trim().is_empty() => h,
+        _ => title,
+    };
+
+    head.split_whitespace()
+        .filter(|w| {
+            let w = w.trim_end_matches('.').to_lowercase();
+            !CHAPTER.contains(&w.as_str())
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(text: &str) -> ChapterNumber {
+        ChapterNumber::parse(text).unwrap()
+    }
+
+    #[test]
+    fn parses_titles() {
+        assert_eq!(
+            num("Vol.2 Chapter 10.5 Part 2"),
+            ChapterNumber {
+                volume: Some(2),
+                number: Some(10),
+                fraction: 500,
+                part: Some(2),
+                ..Default::default()
+            }
+        );
+        assert_eq!(num("Chapter 7: The 3 Kings").number, Some(7));
+        assert_eq!(num("Ch. 10.05").fraction, 50);
+        assert_eq!(num("#12").number, Some(12));
+        assert!(num("Extra 3").extra);
+        assert!(num("Oneshot").special);
+        assert_eq!(ChapterNumber::parse("The Beginning"), None);
+    }
+
+    #[test]
+    fn parses_urls() {
+        assert_eq!(
+            ChapterNumber::from_url("https://example.com/manga/abc/chapter-10-5/").unwrap(),
+            num("10.5")
+        );
+        assert_eq!(
+            ChapterNumber::from_url("https://example.com/manga/abc/chapter_12")
+                .unwrap()
+                .number,
+            Some(12)
+        );
+    }
+
+    #[test]
+    fn display_is_canonical_and_round_trips() {
+        for (text, shown) in [
+            ("Chapter 10.50", "10.5"),
+            ("Vol.2 Chapter 10", "vol 2 10"),
+            ("Vol.2 Chapter 10.5 Part 2", "vol 2 10.5 part 2"),
+            ("Volume 3", "vol 3"),
+            ("Vol 1 Extra 3", "vol 1 extra 3"),
+            ("Special", "special"),
+        ] {
+            let n = num(text);
+            assert_eq!(n.to_string(), shown);
+            assert_eq!(num(n.to_string().as_str()), n);
+        }
+    }
+
+    #[test]
+    fn number_text_keeps_the_site_wording() {
+        assert_eq!(number_text("Chapter 10.5"), "10.5");
+        assert_eq!(number_text("Vol.2 Chapter 10: The Return"), "Vol.2 10");
+        assert_eq!(number_text("Ch. 7 Part 2"), "7 Part 2");
+        assert_eq!(number_text("Extra 3"), "Extra 3");
+    }
+
+    #[test]
+    fn same_number_in_different_volumes_differs() {
+        assert_ne!(
+            num("Vol.1 Chapter 1").to_string(),
+            num("Vol.2 Chapter 1").to_string()
+        );
+    }
+
+    #[test]
+    fn orders_by_volume_then_number() {
+        let mut v = [
+            num("Chapter 30"),
+            num("Vol.2 Chapter 1"),
+            num("Vol.1 Chapter 10 Extra"),
+            num("Vol.1 Chapter 10 Part 2"),
+            num("Vol.1 Chapter 10"),
+            num("Vol.1 Chapter 9.5"),
+        ];
+        v.sort();
+        let shown: Vec<String> = v.iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            shown,
+            vec![
+                "vol 1 9.5",
+                "vol 1 10",
+                "vol 1 10 part 2",
+                "vol 1 extra 10",
+                "vol 2 1",
+                "30"
+            ]
+        );
+    }
+}
@@ -0,0 +1,424 @@
+use std::collections::HashSet;
+
+use sqlx::types::chrono::{
+    DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc,
+};
+
+use crate::{MSError, Result};
+
+/// Words a site uses in its dates. Month names are mapped onto English ones before the
+/// chrono formats are tried, so formats are always written with `%b`/`%B`.
+#[derive(Debug)]
+pub struct DateLocale {
+    /// Full and abbreviated name of every month, January first
+    pub months: [(&'static str, &'static str); 12],
+    pub now: &'static [&'static str],
+    pub today: &'static [&'static str],
+    pub yesterday: &'static [&'static str],
+    pub ago: &'static [&'static str],
+    /// Words meaning "one", as in "an hour ago"
+    pub one: &'static [&'static str],
+    pub seconds: &'static [&'static str],
+    pub minutes: &'static [&'static str],
+    pub hours: &'static [&'static str],
+    pub days: &'static [&'static str],
+    pub weeks: &'static [&'static str],
+    pub months_unit: &'static [&'static str],
+    pub years: &'static [&'static str],
+}
+
+pub const ENGLISH: DateLocale = DateLocale {
+    months: [
+        ("january", "jan"),
+        ("february", "feb"),
+        ("march", "mar"),
+        ("april", "apr"),
+        ("may", "may"),
+        ("june", "jun"),
+        ("july", "jul"),
+        ("august", "aug"),
+        ("september", "sep"),
+        ("october", "oct"),
+        ("november", "nov"),
+        ("december", "dec"),
+    ],
+    now: &["just now", "now"],
+    today: &["today"],
+    yesterday: &["yesterday"],
+    ago: &["ago"],
+    one: &["a", "an", "one"],
+    seconds: &["s", "sec", "secs", "second", "seconds"],
+    minutes: &["m", "min", "mins", "minute", "minutes"],
+    hours: &["h", "hr", "hrs", "hour", "hours"],
+    days: &["d", "day", "days"],
+    weeks: &["w", "wk", "wks", "week", "weeks"],
+    months_unit: &["mo", "month", "months"],
+    years: &["y", "yr", "yrs", "year", "years"],
+};
+
+const ENGLISH_MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// How one source writes its dates.
+#[derive(Debug)]
+pub struct DateFormat {
+    pub locale: &'static DateLocale,
+    /// Offset from UTC, in seconds, of the times the site shows
+    pub utc_offset: i32,
+    /// chrono formats tried in order. Formats without a time are read as midnight.
+    pub formats: &'static [&'static str],
+}
+
+/// A date read off a page. Relative ones, such as "3 hours ago", were counted back from the time
+/// they were read, so reading the same phrase later gives a slightly different date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParsedDate {
+    Absolute(DateTime<Utc>),
+    Relative(DateTime<Utc>),
+}
+
+impl ParsedDate {
+    pub fn at(&self) -> DateTime<Utc> {
+        match self {
+            ParsedDate::Absolute(f) | ParsedDate::Relative(f) => *f,
+        }
+    }
+
+    pub fn is_relative(&self) -> bool {
+        matches!(self, ParsedDate::Relative(_))
+    }
+
+    /// The UTC time stored in `updated_at` and `last_updated`
+    pub fn naive_utc(&self) -> NaiveDateTime {
+        self.at().naive_utc()
+    }
+}
+
+/// Which dates of a scraped manga were relative, so a later scrape that only drifted from them
+/// is not taken for a change
+#[derive(Debug, Clone, Default)]
+pub struct RelativeDates {
+    /// The manga's `last_updated`
+    pub last_updated: bool,
+    /// Source urls of the chapters whose `updated_at` was relative
+    pub chapters: HashSet<String>,
+}
+
+fn parse_err(text: &str) -> MSError {
+    MSError {
+        message: format!("Unrecognised date \"{}\"", text),
+        err_type: crate::MSErrorType::TextParseError,
+    }
+}
+
+impl DateFormat {
+    fn offset(&self) -> FixedOffset {
+        FixedOffset::east_opt(self.utc_offset).unwrap_or_else(|| Utc.fix())
+    }
+
+    /// Parses an absolute or relative date as shown by the site
+    pub fn parse(&self, text: &str) -> Result<ParsedDate> {
+        self.parse_at(text, Utc::now())
+    }
+
+    /// Same as `parse`, with relative phrases counted back from `now`. "Today" and "yesterday"
+    /// name a day rather than an age, so they are absolute.
+    pub fn parse_at(&self, text: &str, now: DateTime<Utc>) -> Result<ParsedDate> {
+        let cleaned = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if cleaned.is_empty() {
+            return Err(parse_err(text));
+        }
+
+        let lower = cleaned.to_lowercase();
+
+        if let Some(d) = self.parse_ago(lower.as_str(), now) {
+            return Ok(ParsedDate::Relative(d));
+        }
+
+        self.parse_day(lower.as_str(), now)
+            .or_else(|| self.parse_absolute(cleaned.as_str()))
+            .map(ParsedDate::Absolute)
+            .ok_or_else(|| parse_err(text))
+    }
+
+    fn local(&self, naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+        self.offset()
+            .from_local_datetime(&naive)
+            .single()
+            .map(|f| f.with_timezone(&Utc))
+    }
+
+    fn parse_absolute(&self, text: &str) -> Option<DateTime<Utc>> {
+        let text = self.englishize(text);
+
+        self.formats.iter().find_map(|fmt| {
+            NaiveDateTime::parse_from_str(text.as_str(), fmt)
+                .ok()
+                .or_else(|| {
+                    NaiveDate::parse_from_str(text.as_str(), fmt)
+                        .ok()
+                        .and_then(|d| d.and_hms_opt(0, 0, 0))
+                })
+                .and_then(|f| self.local(f))
+        })
+    }
+
+    /// Swaps localized month names for English ones, abbreviations for abbreviations since `%b`
+    /// does not read a full name
+    fn englishize(&self, text: &str) -> String {
+        text.split(' ')
+            .map(|w| {
+                let lw = w.to_lowercase();
+                let (word, rest) = match lw.find(|c: char| !c.is_alphabetic()) {
+                    Some(i) => lw.split_at(i),
+                    None => (lw.as_str(), ""),
+                };
+                if word.is_empty() {
+                    return w.to_string();
+                }
+                let months = self.locale.months.iter();
+                if let Some(i) = months.clone().position(|(full, _)| *full == word) {
+                    format!("{}{}", ENGLISH_MONTHS[i], rest)
+                } else if let Some(i) = months.clone().position(|(_, short)| *short == word) {
+                    format!("{}{}", &ENGLISH_MONTHS[i][..3], rest)
+                } else {
+                    w.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// "today" or "yesterday", optionally followed by a time
+    fn parse_day(&self, text: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let l = self.locale;
+        let words: Vec<&str> = text.split(' ').collect();
+
+        let back = if l.today.contains(&words[0]) {
+            0
+        } else if l.yesterday.contains(&words[0]) {
+            1
+        } else {
+            return None;
+        };
+
+        let local_now = now.with_timezone(&self.offset());
+        let time = match words.get(1..).map(|f| f.join(" ")) {
+            Some(t) if !t.is_empty() => parse_time(t.as_str())?,
+            _ => NaiveTime::from_hms_opt(0, 0, 0)?,
+        };
+        let day = local_now.naive_local().date() - Duration::days(back);
+        self.local(day.and_time(time))
+    }
+
+    /// "just now", "<n> <unit> ago", "<n><unit> ago" or "an hour ago"
+    fn parse_ago(&self, text: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let l = self.locale;
+
+        if l.now.contains(&text) {
+            return Some(now);
+        }
+
+        let words: Vec<&str> = text.split(' ').filter(|f| !l.ago.contains(f)).collect();
+
+        let (n, unit) = match words.as_slice() {
+            [n, unit] => (*n, *unit),
+            [both] => {
+                let i = both.find(|c: char| !c.is_ascii_digit())?;
+                both.split_at(i)
+            }
+            _ => return None,
+        };
+
+        let n: i64 = if l.one.contains(&n) {
+            1
+        } else {
+            n.parse().ok().filter(|f| (0..=MAX_AGO).contains(f))?
+        };
+
+        let d = if l.seconds.contains(&unit) {
+            Duration::seconds(n)
+        } else if l.minutes.contains(&unit) {
+            Duration::minutes(n)
+        } else if l.hours.contains(&unit) {
+            Duration::hours(n)
+        } else if l.days.contains(&unit) {
+            Duration::days(n)
+        } else if l.weeks.contains(&unit) {
+            Duration::weeks(n)
+        } else if l.months_unit.contains(&unit) {
+            Duration::days(30 * n)
+        } else if l.years.contains(&unit) {
+            Duration::days(365 * n)
+        } else {
+            return None;
+        };
+
+        now.checked_sub_signed(d)
+    }
+}
+
+/// Anything further back than this many units is garbage, and would overflow `Duration`
+const MAX_AGO: i64 = 100_000;
+
+/// Unit a site writes a date this old in, "3 hours ago" rather than "200 minutes ago"
+fn relative_unit(age: Duration) -> Duration {
+    if age < Duration::hours(1) {
+        Duration::minutes(1)
+    } else if age < Duration::days(1) {
+        Duration::hours(1)
+    } else if age < Duration::weeks(1) {
+        Duration::days(1)
+    } else if age < Duration::days(30) {
+        Duration::weeks(1)
+    } else if age < Duration::days(365) {
+        Duration::days(30)
+    } else {
+        Duration::days(365)
+    }
+}
+
+/// Whether a scraped date tells nothing new about the stored one. A relative phrase read again
+/// later lands anywhere within its unit of the first reading, "3 hours ago" becomes "4 hours ago"
+/// an hour on, so a `relative` date only counts as changed once it moves further than that.
+pub fn same_date(
+    stored: Option<NaiveDateTime>,
+    scraped: Option<NaiveDateTime>,
+    relative: bool,
+    now: NaiveDateTime,
+) -> bool {
+    match (stored, scraped) {
+        (Some(s), Some(n)) if s != n && relative => {
+            (n - s).num_seconds().abs() <= relative_unit(now - s.min(n)).num_seconds()
+        }
+        (s, n) => s == n,
+    }
+}
+
+fn parse_time(t: &str) -> Option<NaiveTime> {
+    ["%H:%M", "%H:%M:%S", "%I:%M %p", "%I:%M%p"]
+        .iter()
+        .find_map(|fmt| NaiveTime::parse_from_str(t, fmt).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SITE: DateFormat = DateFormat {
+        locale: &ENGLISH,
+        utc_offset: 0,
+        formats: &["%b %d,%Y - %H:%M", "%d %B %Y"],
+    };
+
+    fn naive(y: i32, m: u32, d: u32, h: u32, mi: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .and_then(|f| f.and_hms_opt(h, mi, 0))
+            .unwrap()
+    }
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&naive(y, m, d, h, mi))
+    }
+
+    /// Noon on the day of the tests, with the fraction of a second a real clock has
+    fn now() -> DateTime<Utc> {
+        utc(2026, 10, 18, 12, 0) + Duration::milliseconds(250)
+    }
+
+    fn read(text: &str) -> ParsedDate {
+        SITE.parse_at(text, now()).unwrap()
+    }
+
+    fn parse(text: &str) -> DateTime<Utc> {
+        read(text).at()
+    }
+
+    #[test]
+    fn parses_absolute_dates() {
+        assert_eq!(parse("Oct 18,2026 - 14:30"), utc(2026, 10, 18, 14, 30));
+        assert_eq!(parse("18 October 2026"), utc(2026, 10, 18, 0, 0));
+        assert_eq!(parse("  18   OCTOBER 2026 "), utc(2026, 10, 18, 0, 0));
+        assert!(!read("18 October 2026").is_relative());
+    }
+
+    #[test]
+    fn applies_the_site_offset() {
+        let site = DateFormat {
+            utc_offset: 3600,
+            ..SITE
+        };
+        assert_eq!(
+            site.parse_at("18 October 2026", now()).unwrap().at(),
+            utc(2026, 10, 17, 23, 0)
+        );
+    }
+
+    #[test]
+    fn parses_relative_dates() {
+        assert_eq!(parse("just now"), now());
+        assert_eq!(parse("3 hours ago"), now() - Duration::hours(3));
+        assert_eq!(parse("an hour ago"), now() - Duration::hours(1));
+        assert_eq!(parse("5d ago"), now() - Duration::days(5));
+        assert_eq!(parse("2 Weeks Ago"), now() - Duration::weeks(2));
+        assert!(read("3 hours ago").is_relative());
+        assert!(read("just now").is_relative());
+    }
+
+    #[test]
+    fn parses_today_and_yesterday() {
+        assert_eq!(parse("today"), utc(2026, 10, 18, 0, 0));
+        assert_eq!(parse("today 14:30"), utc(2026, 10, 18, 14, 30));
+        assert_eq!(parse("yesterday 9:05 PM"), utc(2026, 10, 17, 21, 5));
+        assert!(!read("yesterday").is_relative());
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(SITE.parse_at("", now()).is_err());
+        assert!(SITE.parse_at("soon", now()).is_err());
+        assert!(SITE.parse_at("3 fortnights ago", now()).is_err());
+        assert!(SITE.parse_at("999999 years ago", now()).is_err());
+        assert!(SITE.parse_at("32 October 2026", now()).is_err());
+    }
+
+    #[test]
+    fn relative_dates_drifting_within_their_unit_are_the_same() {
+        let now = now().naive_utc();
+        let stored = Some(naive(2026, 10, 18, 9, 0));
+
+        //read as "3 hours ago" at 12:00:00.25 and again 40 minutes later
+        let drifted = Some(naive(2026, 10, 18, 9, 40) + Duration::milliseconds(250));
+        assert!(same_date(stored, drifted, true, now));
+
+        let moved = Some(naive(2026, 10, 18, 10, 30) + Duration::milliseconds(250));
+        assert!(!same_date(stored, moved, true, now));
+    }
+
+    #[test]
+    fn absolute_dates_must_match_exactly() {
+        let now = now().naive_utc();
+        let stored = Some(naive(2026, 10, 18, 9, 0));
+
+        let moved = Some(naive(2026, 10, 18, 9, 40));
+
+        assert!(same_date(stored, stored, false, now));
+        assert!(!same_date(stored, moved, false, now));
+        assert!(!same_date(stored, None, false, now));
+        assert!(!same_date(None, stored, false, now));
+        assert!(same_date(None, None, false, now));
+    }
+}
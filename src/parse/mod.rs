@@ -0,0 +1,4 @@
+pub mod chapter;
+pub mod date;
+pub mod status;
+pub mod text;
@@ -0,0 +1,163 @@
+use std::fmt::Display;
+
+/// Publication status of a series, whatever the site calls it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Status {
+    Ongoing,
+    Completed,
+    Hiatus,
+    Cancelled,
+    #[default]
+    Unknown,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Ongoing => "ongoing",
+            Status::Completed => "completed",
+            Status::Hiatus => "hiatus",
+            Status::Cancelled => "cancelled",
+            Status::Unknown => "unknown",
+        }
+    }
+
+    /// Reads back a value written by `as_str`
+    pub fn from_code(code: &str) -> Status {
+        match code {
+            "ongoing" => Status::Ongoing,
+            "completed" => Status::Completed,
+            "hiatus" => Status::Hiatus,
+            "cancelled" => Status::Cancelled,
+            _ => Status::Unknown,
+        }
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Spellings shared by most sites, tried after the source's own table
+const COMMON: [(&str, Status); 10] = [
+    ("ongoing", Status::Ongoing),
+    ("completed", Status::Completed),
+    ("complete", Status::Completed),
+    ("finished", Status::Completed),
+    ("hiatus", Status::Hiatus),
+    ("on hiatus", Status::Hiatus),
+    ("cancelled", Status::Cancelled),
+    ("canceled", Status::Cancelled),
+    ("dropped", Status::Cancelled),
+    ("discontinued", Status::Cancelled),
+];
+
+const MANGANELO: [(&str, Status); 2] = [
+    ("ongoing", Status::Ongoing),
+    ("completed", Status::Completed),
+];
+
+const READM: [(&str, Status); 3] = [
+    ("ongoing", Status::Ongoing),
+    ("completed", Status::Completed),
+    ("not available", Status::Unknown),
+];
+
+const MANGADINO: [(&str, Status); 3] = [
+    ("ongoing", Status::Ongoing),
+    ("completed", Status::Completed),
+    ("", Status::Unknown),
+];
+
+const STUDYGROUP: [(&str, Status); 3] = [
+    ("ongoing", Status::Ongoing),
+    ("completed", Status::Completed),
+    ("on hiatus", Status::Hiatus),
+];
+
+/// How a source's status texts map to `Status`, by source name
+pub fn source_statuses(source: &str) -> &'static [(&'static str, Status)] {
+    match source {
+        "manganelo" => &MANGANELO,
+        "readm" => &READM,
+        "mangadino" => &MANGADINO,
+        "studygroup" => &STUDYGROUP,
+        _ => &[],
+    }
+}
+
+/// Maps the raw status text scraped from `source` to a `Status`. Texts no table knows are
+/// logged and come back as `Unknown`.
+pub fn normalize_status(source: &str, raw: &str) -> Status {
+    let key = raw
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+
+    match source_statuses(source)
+        .iter()
+        .chain(COMMON.iter())
+        .find(|(text, _)| *text == key)
+    {
+        Some((_, s)) => *s,
+        None => {
+            if !key.is_empty() {
+                println!("Unmapped status \"{}\" from {}", raw, source);
+            }
+            Status::Unknown
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_source_spellings() {
+        assert_eq!(normalize_status("manganelo", "Ongoing"), Status::Ongoing);
+        assert_eq!(normalize_status("readm", "Not  Available"), Status::Unknown);
+        assert_eq!(
+            normalize_status("studygroup", " On Hiatus "),
+            Status::Hiatus
+        );
+        assert_eq!(normalize_status("mangadino", ""), Status::Unknown);
+    }
+
+    #[test]
+    fn falls_back_to_common_spellings() {
+        assert_eq!(normalize_status("readm", "Finished"), Status::Completed);
+        assert_eq!(normalize_status("manganelo", "Dropped"), Status::Cancelled);
+        assert_eq!(
+            normalize_status("unknown-site", "canceled"),
+            Status::Cancelled
+        );
+    }
+
+    #[test]
+    fn unmapped_text_is_unknown() {
+        assert_eq!(
+            normalize_status("manganelo", "Coming soon"),
+            Status::Unknown
+        );
+        assert_eq!(normalize_status("unknown-site", ""), Status::Unknown);
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        for s in [
+            Status::Ongoing,
+            Status::Completed,
+            Status::Hiatus,
+            Status::Cancelled,
+            Status::Unknown,
+        ] {
+            assert_eq!(Status::from_code(s.as_str()), s);
+            assert_eq!(s.to_string(), s.as_str());
+        }
+        assert_eq!(Status::from_code("garbage"), Status::Unknown);
+    }
+}
@@ -0,0 +1,239 @@
+use mangaverse_entity::models::manga::MangaTable;
+use unicode_segmentation::UnicodeSegmentation;
+
+const ELLIPSIS: char = '…';
+
+fn named_entity(name: &str) -> Option<char> {
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        _ => return None,
+    })
+}
+
+/// Decodes HTML entities, named and numeric. Unknown ones are left as they are.
+pub fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        let decoded = rest[1..].find(';').filter(|e| *e <= 10).and_then(|e| {
+            let body = &rest[1..e + 1];
+            let c = match body.strip_prefix('#') {
+                Some(n) => match n.strip_prefix(['x', 'X']) {
+                    Some(h) => u32::from_str_radix(h, 16).ok(),
+                    None => n.parse().ok(),
+                }
+                .and_then(char::from_u32),
+                None => named_entity(body),
+            };
+            c.map(|c| (c, e + 2))
+        });
+
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Turns `<br>` and paragraph tags into line breaks and drops every other tag
+pub fn strip_tags(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(i) = rest.find('<') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        let looks_like_tag = matches!(
+            rest[1..].chars().next(),
+            Some(c) if c.is_ascii_alphabetic() || c == '/' || c == '!'
+        );
+
+        match rest.find('>').filter(|_| looks_like_tag) {
+            Some(e) => {
+                let tag = rest[1..e].trim_start_matches('/').to_lowercase();
+                let name = tag
+                    .split(|c: char| c.is_whitespace() || c == '/')
+                    .next()
+                    .unwrap_or_default();
+                if name == "br" || name == "p" {
+                    out.push('\n');
+                }
+                rest = &rest[e + 1..];
+            }
+            None => {
+                out.push('<');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Text of an HTML fragment such as an element's `inner_html`, with tags handled by `strip_tags`
+/// and entities decoded. Text collected with `.text()` is decoded already and must not go
+/// through this again, or a literal "&amp;" would turn into "&".
+pub fn html_to_text(s: &str) -> String {
+    decode_entities(strip_tags(s).as_str())
+}
+
+/// One-line text such as a name or title, with all whitespace collapsed to single spaces
+pub fn clean_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Multi-line text such as a description: like `clean_line` within each line, keeping single
+/// line breaks and at most one blank line between paragraphs
+pub fn clean_text(s: &str) -> String {
+    let mut out: Vec<String> = Vec::new();
+
+    for line in s.lines() {
+        let l = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if l.is_empty() && !matches!(out.last(), Some(p) if !p.is_empty()) {
+            continue;
+        }
+        out.push(l);
+    }
+
+    while matches!(out.last(), Some(l) if l.is_empty()) {
+        out.pop();
+    }
+
+    out.join("\n")
+}
+
+/// Cuts `s` to at most `max_chars` characters without splitting a grapheme, ending it with an
+/// ellipsis when anything was cut
+pub fn truncate(s: &str, max_chars: usize) -> String {
+    if s.chars().count() <= max_chars {
+        return s.to_string();
+    }
+
+    let budget = max_chars.saturating_sub(1);
+    let mut out = String::new();
+    let mut used = 0;
+
+    for g in s.graphemes(true) {
+        let n = g.chars().count();
+        if used + n > budget {
+            break;
+        }
+        used += n;
+        out.push_str(g);
+    }
+
+    let trimmed = out.trim_end().len();
+    out.truncate(trimmed);
+
+    if max_chars > 0 {
+        out.push(ELLIPSIS);
+    }
+    out
+}
+
+/// Runs a freshly scraped manga's name, titles, description and chapter names through the
+/// cleaners above. Sources call this before handing the manga back, on text that is already
+/// decoded; anything read as HTML goes through `html_to_text` first.
+pub fn normalize_manga_text(mng: &mut MangaTable<'_>) {
+    mng.name = clean_line(mng.name.as_str());
+    mng.description = clean_text(mng.description.as_str());
+
+    for t in mng.titles.iter_mut() {
+        *t = clean_line(t.as_str());
+    }
+
+    for c in mng.chapters.iter_mut() {
+        c.chapter_name = clean_line(c.chapter_name.as_str());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(decode_entities("Tom &amp; Jerry"), "Tom & Jerry");
+        assert_eq!(decode_entities("&#39;quoted&#x27;"), "'quoted'");
+        assert_eq!(decode_entities("&ldquo;Hi&rdquo;&hellip;"), "“Hi”…");
+        assert_eq!(decode_entities("AT&T &bogus; &"), "AT&T &bogus; &");
+        assert_eq!(decode_entities("&#xZZ; &#99999999;"), "&#xZZ; &#99999999;");
+    }
+
+    #[test]
+    fn strips_tags() {
+        assert_eq!(strip_tags("a<br>b<br/>c"), "a\nb\nc");
+        assert_eq!(strip_tags("<p>one</p><p>two</p>"), "\none\n\ntwo\n");
+        assert_eq!(strip_tags("<b>bold</b> <!-- note -->text"), "bold text");
+        assert_eq!(strip_tags("1 < 2 and 3 > 2"), "1 < 2 and 3 > 2");
+    }
+
+    #[test]
+    fn html_is_decoded_once() {
+        assert_eq!(
+            html_to_text("Tom &amp;amp; <i>Jerry</i>"),
+            "Tom &amp; Jerry"
+        );
+        assert_eq!(clean_line("Tom &amp; Jerry"), "Tom &amp; Jerry");
+    }
+
+    #[test]
+    fn cleans_lines() {
+        assert_eq!(clean_line("  Solo \n\t Leveling  "), "Solo Leveling");
+        assert_eq!(clean_line("\u{a0}"), "");
+    }
+
+    #[test]
+    fn cleans_text() {
+        assert_eq!(
+            clean_text("  First   line\nsecond line\n\n\n\nNext  paragraph\n\n"),
+            "First line\nsecond line\n\nNext paragraph"
+        );
+        assert_eq!(clean_text("\n\n  \nText"), "Text");
+    }
+
+    #[test]
+    fn truncates_by_characters() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("exactly", 7), "exactly");
+        assert_eq!(truncate("a long description", 7), "a long…");
+        assert_eq!(truncate("日本語のテキスト", 4), "日本語…");
+        assert_eq!(truncate("anything", 0), "");
+    }
+
+    #[test]
+    fn truncation_keeps_graphemes_whole() {
+        let s = "ok 👨‍👩‍👧 family";
+        let t = truncate(s, 6);
+        assert_eq!(t, "ok…");
+        assert!(t.chars().count() <= 6);
+        assert_eq!(truncate("e\u{301}e\u{301}e\u{301}", 4), "e\u{301}…");
+    }
+}
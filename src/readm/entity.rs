@@ -1,16 +1,18 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 use mangaverse_entity::models::{
-    chapter::ChapterTable, genre::Genre, manga::MangaTable, page::PageTable, source::SourceTable,
+    chapter::ChapterTable, manga::MangaTable, page::PageTable, source::SourceTable,
 };
+use reqwest::Url;
 use scraper::{Html, Selector};
-use sqlx::{
-    types::chrono::{NaiveDateTime, Utc},
-    MySql, Pool,
-};
+use sqlx::{types::chrono::Utc, MySql, Pool};
 
+use crate::parse::chapter::{number_text, ChapterNumber};
+use crate::parse::date::{DateFormat, RelativeDates, ENGLISH};
+use crate::parse::text::normalize_manga_text;
 use crate::{MSError, Result};
 
+use crate::db::genre::GenreMap;
 use crate::db::source::insert_source_if_not_exists;
 
 use lazy_static::lazy_static;
@@ -19,6 +21,12 @@ const SOURCE_NAME: &str = "readm";
 
 const WEBSITE_HOST: &str = "https://readm.org";
 
+const DATES: DateFormat = DateFormat {
+    locale: &ENGLISH,
+    utc_offset: 0,
+    formats: &["%d %B %Y"],
+};
+
 lazy_static! {
     static ref GENRE_SELECTOR: Selector =
         Selector::parse("ul.advanced-search-categories li").unwrap();
@@ -41,40 +49,66 @@ pub async fn get_readm_source(pool: &Pool<MySql>) -> Result<SourceTable> {
     insert_source_if_not_exists(SOURCE_NAME, 1, pool).await
 }
 
-pub async fn get_readm_genres() -> Result<HashSet<String>> {
+/// Genre names offered by the site along with the page listing each genre
+pub async fn get_readm_genres() -> Result<HashMap<String, Option<String>>> {
     let url = "https://readm.org/advanced-search";
 
     let response_text = reqwest::get(url).await?.text().await?;
 
-    let doc = Html::parse_document(&response_text);
+    Ok(parse_genres(&Html::parse_document(&response_text)))
+}
 
-    Ok(doc
-        .select(&GENRE_SELECTOR)
+pub fn parse_genres(doc: &Html) -> HashMap<String, Option<String>> {
+    doc.select(&GENRE_SELECTOR)
         .filter_map(|f| {
             let r = f.text().collect::<String>().trim().to_lowercase();
             if r == "uncategorized" {
                 None
             } else {
-                Some(r)
+                let browse = format!("{}/category/{}", WEBSITE_HOST, r.replace(' ', "-"));
+                Some((r, Some(browse)))
             }
         })
-        .collect())
+        .collect()
 }
 
-#[allow(unused_must_use)]
+/// Reads the manga along with its chapters, and which chapter dates were relative
 pub async fn get_manga<'a>(
     url: String,
     sc: &'a SourceTable,
-    map: &'a HashMap<String, Genre>,
+    map: &'a GenreMap,
+) -> Result<(MangaTable<'a>, RelativeDates)> {
+    let text = reqwest::get(url.as_str()).await?.text().await?;
+
+    let mut mng = parse_manga(&Html::parse_document(text.as_str()), url, sc, map)?;
+    let mut relative = RelativeDates::default();
+
+    for yt in mng.chapters.iter_mut() {
+        let r = yt.chapter_id.clone();
+        match populate_chapter(yt, r.as_str()).await {
+            Ok(true) => {
+                relative.chapters.insert(r);
+            }
+            Ok(false) => {}
+            Err(e) => println!("Failed to load chapter {}: {}", r, e.message),
+        }
+    }
+
+    Ok((mng, relative))
+}
+
+/// Reads a manga page into everything but the chapters' contents
+pub fn parse_manga<'a>(
+    doc: &Html,
+    url: String,
+    sc: &'a SourceTable,
+    map: &'a GenreMap,
 ) -> Result<MangaTable<'a>> {
     let mut mng: MangaTable = MangaTable::new(sc);
     mng.is_listed = true;
     mng.url = url;
 
     {
-        let doc =
-            Html::parse_document(reqwest::get(mng.url.as_str()).await?.text().await?.as_str());
-
         mng.name.extend(
             doc.select(&NAME_SELECTOR)
                 .next()
@@ -89,17 +123,17 @@ pub async fn get_manga<'a>(
 
         mng.titles.push(mng.name.clone());
 
-        mng.cover_url.push_str(WEBSITE_HOST);
-
-        mng.cover_url.push_str(
-            doc.select(&COVERURL_SELECTOR)
-                .next()
-                .and_then(|f| f.value().attr("src"))
-                .ok_or(MSError {
-                    message: "Failed to get cover url link".to_string(),
-                    err_type: crate::MSErrorType::TextParseError,
-                })?,
-        );
+        //usually relative to the site, but absolute CDN links turn up too
+        mng.cover_url = doc
+            .select(&COVERURL_SELECTOR)
+            .next()
+            .and_then(|f| f.value().attr("src"))
+            .and_then(|f| Url::parse(WEBSITE_HOST).and_then(|u| u.join(f)).ok())
+            .ok_or(MSError {
+                message: "Failed to get cover url link".to_string(),
+                err_type: crate::MSErrorType::TextParseError,
+            })?
+            .to_string();
 
         if let Some(x) = doc.select(&TITLES_SELECTOR).next() {
             mng.titles.extend(
@@ -157,11 +191,6 @@ pub async fn get_manga<'a>(
     }
 
     {
-        for yt in mng.chapters.iter_mut() {
-            let r = yt.chapter_id.clone();
-            populate_chapter(yt, r.as_str()).await;
-        }
-
         mng.chapters.reverse();
 
         let sz = mng.chapters.len() as i32;
@@ -171,21 +200,38 @@ pub async fn get_manga<'a>(
         }
     }
 
+    normalize_manga_text(&mut mng);
+
     Ok(mng)
 }
 
-async fn populate_chapter(t: &mut ChapterTable, x: &str) -> Result<()> {
-    let y = Html::parse_document(reqwest::get(x).await?.text().await?.as_str());
-    if let Some(dt) = y.select(&CHAPTER_UPDATED_AT_SELECTOR).next() {
-        let mut u = dt.text().collect::<String>().trim().to_string();
-        u.push_str(" 00:00:00");
+async fn populate_chapter(t: &mut ChapterTable, x: &str) -> Result<bool> {
+    let text = reqwest::get(x).await?.text().await?;
+    Ok(parse_chapter(t, &Html::parse_document(text.as_str()), x))
+}
 
-        t.updated_at = NaiveDateTime::parse_from_str(u.as_str(), "%d %B %Y %T").ok();
+/// Fills in the date, number and pages of a chapter from its page at `x`. Returns whether the
+/// date was relative.
+pub fn parse_chapter(t: &mut ChapterTable, y: &Html, x: &str) -> bool {
+    let mut relative = false;
+    if let Some(dt) = y.select(&CHAPTER_UPDATED_AT_SELECTOR).next() {
+        match DATES.parse(dt.text().collect::<String>().as_str()) {
+            Ok(d) => {
+                t.updated_at = Some(d.naive_utc());
+                relative = d.is_relative();
+            }
+            Err(e) => println!("{}", e.message),
+        }
     }
     if let Some(dt) = y.select(&CHAPTER_NUMBER_SELECTOR).next() {
-        t.chapter_number = dt.text().collect::<String>();
-        if t.chapter_number.len() > 8 {
-            t.chapter_number = t.chapter_number[8..].to_string();
+        let text = dt.text().collect::<String>();
+        if ChapterNumber::parse(text.as_str()).is_some() {
+            t.chapter_number = number_text(text.as_str());
+        }
+    }
+    if t.chapter_number.is_empty() {
+        if let Some(n) = ChapterNumber::from_url(x) {
+            t.chapter_number = n.to_string();
         }
     }
     for (idxn, f) in y.select(&IMAGES_SELECTOR).enumerate() {
@@ -199,5 +245,5 @@ async fn populate_chapter(t: &mut ChapterTable, x: &str) -> Result<()> {
             t.pages.push(r);
         }
     }
-    Ok(())
+    relative
 }
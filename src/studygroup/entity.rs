@@ -1,14 +1,17 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 use mangaverse_entity::models::{
-    chapter::ChapterTable, genre::Genre, manga::MangaTable, page::PageTable, source::SourceTable,
+    chapter::ChapterTable, manga::MangaTable, page::PageTable, source::SourceTable,
 };
 use scraper::{Html, Selector};
 use sqlx::types::chrono::Utc;
 use sqlx::{MySql, Pool};
 
+use crate::parse::chapter::ChapterNumber;
+use crate::parse::text::normalize_manga_text;
 use crate::{MSError, Result};
 
+use crate::db::genre::GenreMap;
 use crate::db::source::insert_source_if_not_exists;
 
 use lazy_static::lazy_static;
@@ -35,13 +38,16 @@ pub async fn get_studygroup_source(pool: &Pool<MySql>) -> Result<SourceTable> {
     insert_source_if_not_exists(SOURCE_NAME, 0, pool).await
 }
 
-pub async fn get_studygroup_genres() -> Result<HashSet<String>> {
+/// Genres of the one series the site hosts. There are no genre pages to browse.
+pub async fn get_studygroup_genres() -> Result<HashMap<String, Option<String>>> {
     let url = WEBSITE_HOST;
 
     let response_text = reqwest::get(url).await?.text().await?;
 
-    let doc = Html::parse_document(&response_text);
+    parse_genres(&Html::parse_document(&response_text))
+}
 
+pub fn parse_genres(doc: &Html) -> Result<HashMap<String, Option<String>>> {
     let labels = doc.select(&TABLE_LABEL_SELECTOR);
     let vals = doc.select(&TABLE_VALUE_SELECTOR);
 
@@ -54,7 +60,7 @@ pub async fn get_studygroup_genres() -> Result<HashSet<String>> {
                     .collect::<String>()
                     .split('-')
                     .map(str::trim)
-                    .map(ToString::to_string)
+                    .map(|f| (f.to_string(), None))
                     .collect(),
             )
         } else {
@@ -62,25 +68,42 @@ pub async fn get_studygroup_genres() -> Result<HashSet<String>> {
         }
     })
     .ok_or(MSError {
-        message: "Failed to get cover url link".to_string(),
+        message: "Failed to get genres".to_string(),
         err_type: crate::MSErrorType::TextParseError,
     })
 }
 
-#[allow(unused_must_use)]
 pub async fn get_manga<'a>(
     url: String,
     sc: &'a SourceTable,
-    map: &'a HashMap<String, Genre>,
+    map: &'a GenreMap,
+) -> Result<MangaTable<'a>> {
+    let text = reqwest::get(url.as_str()).await?.text().await?;
+
+    let mut mng = parse_manga(&Html::parse_document(text.as_str()), url, sc, map)?;
+
+    for yt in mng.chapters.iter_mut() {
+        let r = yt.chapter_id.clone();
+        if let Err(e) = populate_chapter(yt, r.as_str()).await {
+            println!("Failed to load chapter {}: {}", r, e.message);
+        }
+    }
+
+    Ok(mng)
+}
+
+/// Reads the series page into everything but the chapters' pages
+pub fn parse_manga<'a>(
+    doc: &Html,
+    url: String,
+    sc: &'a SourceTable,
+    map: &'a GenreMap,
 ) -> Result<MangaTable<'a>> {
     let mut mng: MangaTable = MangaTable::new(sc);
     mng.is_listed = true;
     mng.url = url;
 
     {
-        let doc =
-            Html::parse_document(reqwest::get(mng.url.as_str()).await?.text().await?.as_str());
-
         mng.name = String::from("Study Group");
 
         mng.titles.push(mng.name.clone());
@@ -101,7 +124,6 @@ pub async fn get_manga<'a>(
         let metadata_table = iter_label.zip(iter_value);
 
         for (label, value) in metadata_table {
-            println!("{:#?}", value.text().collect::<String>().split(" - ").map(str::trim).map(str::to_lowercase).collect::<Vec<String>>());
             match label.text().collect::<String>().as_str() {
                 AUTHOR => mng.authors.extend(
                     value
@@ -155,6 +177,9 @@ pub async fn get_manga<'a>(
                 };
 
                 t.chapter_id = x.to_string();
+                t.chapter_number = ChapterNumber::from_url(x)
+                    .map(|f| f.to_string())
+                    .unwrap_or_default();
 
                 mng.chapters.push(t);
             }
@@ -162,11 +187,6 @@ pub async fn get_manga<'a>(
     }
 
     {
-        for yt in mng.chapters.iter_mut() {
-            let r = yt.chapter_id.clone();
-            populate_chapter(yt, r.as_str()).await;
-        }
-
         mng.chapters.reverse();
 
         let sz = mng.chapters.len() as i32;
@@ -176,13 +196,19 @@ pub async fn get_manga<'a>(
         }
     }
 
-    println!("{:#?}", mng);
+    normalize_manga_text(&mut mng);
 
     Ok(mng)
 }
 
 async fn populate_chapter(t: &mut ChapterTable, x: &str) -> Result<()> {
-    let y = Html::parse_document(reqwest::get(x).await?.text().await?.as_str());
+    let text = reqwest::get(x).await?.text().await?;
+    t.pages = parse_pages(&Html::parse_document(text.as_str()));
+    Ok(())
+}
+
+pub fn parse_pages(y: &Html) -> Vec<PageTable> {
+    let mut pages = Vec::new();
     for (idxn, f) in y.select(&IMAGES_SELECTOR).enumerate() {
         if let Some(dt) = f.value().attr("src") {
             let mut r = PageTable {
@@ -190,8 +216,8 @@ async fn populate_chapter(t: &mut ChapterTable, x: &str) -> Result<()> {
                 ..Default::default()
             };
             r.url.push_str(dt);
-            t.pages.push(r);
+            pages.push(r);
         }
     }
-    Ok(())
+    pages
 }